@@ -0,0 +1,155 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap}, fmt::Write, str::FromStr};
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{events::{Event, Events}, table::{Cell, Row, SortValue, Table}, time::{self, DAY, WEEK, YEAR}, Entries};
+
+/// A span of time from `start` up to, but not including, `end`
+#[derive(Clone,Copy,Debug)]
+pub struct TimeRange {
+	pub start: u64,
+	pub end: u64,
+}
+
+impl TimeRange {
+	fn contains(&self, event: &Event) -> bool {
+		(self.start..self.end).contains(&event.time)
+	}
+}
+
+impl std::fmt::Display for TimeRange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}..{}", time::format_date(self.start), time::format_date(self.end))
+	}
+}
+
+/// Parse one end of a range, either a `YYYY-MM-DD` date or a duration before now like '30d'
+fn parse_instant(s: &str, now: u64) -> Result<u64,String> {
+	match time::parse_duration(s) {
+		Ok(ago) => Ok(now.saturating_sub(ago)),
+		Err(_) => time::parse_date(s)
+	}
+}
+
+impl FromStr for TimeRange {
+	type Err = String;
+	/// Parses `START..END`. Either end can be left out to leave that side open.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let now = time::now();
+		let Some((start, end)) = s.split_once("..") else {
+			return Err(format!("cmdstat: invalid range `{s}', expected START..END"))
+		};
+		let start = if start.is_empty() { 0 } else { parse_instant(start, now)? };
+		let end = if end.is_empty() { now } else { parse_instant(end, now)? };
+		if start >= end {
+			return Err(format!("cmdstat: the range `{s}' ends before it starts"))
+		}
+		Ok(Self { start, end })
+	}
+}
+
+/// Parse a period like 'day', 'week', 'month' or 'year', or any duration like '10d'
+pub fn parse_period(s: &str) -> Result<u64,String> {
+	match s.to_lowercase().as_str() {
+		"day" => Ok(DAY),
+		"week" => Ok(WEEK),
+		"month" => Ok(DAY * 30),
+		"year" => Ok(YEAR),
+		_ => time::parse_duration(s)
+	}
+}
+
+/// The two back to back ranges covering the last `period` and the one before it
+pub fn last_two_periods(period: u64, now: u64) -> (TimeRange, TimeRange) {
+	let mid = now.saturating_sub(period);
+	let a = TimeRange { start: mid.saturating_sub(period), end: mid };
+	let b = TimeRange { start: mid, end: now };
+	(a, b)
+}
+
+/// Count the calls made to each command within `range`, with its rank among them
+fn tally(events: &Events, range: TimeRange, groups: &BTreeMap<String,Vec<String>>) -> HashMap<String,(u32,usize)> {
+	let mut entries = Events(events.0.iter().filter(|ev| range.contains(ev)).cloned().collect()).to_entries();
+	entries.apply_groups(groups);
+	entries.sort_entries();
+	let Entries(entries) = entries;
+	entries.into_iter()
+		.enumerate()
+		.map(|(rank, ent)| (ent.command, (ent.count, rank + 1)))
+		.collect()
+}
+
+/// Render a table comparing each command's calls in range `a` with range `b`, followed by
+/// the commands that were picked up or dropped between them
+pub fn format_compare(events: &Events, a: TimeRange, b: TimeRange, groups: &BTreeMap<String,Vec<String>>, num: usize) -> String {
+	let before = tally(events, a, groups);
+	let after = tally(events, b, groups);
+
+	let mut commands: Vec<&String> = before.keys().chain(after.keys()).collect();
+	commands.sort();
+	commands.dedup();
+	let count = |tally: &HashMap<String,(u32,usize)>, cmd: &str| tally.get(cmd).map(|&(n,_)| n).unwrap_or(0);
+	commands.sort_by_key(|cmd| (Reverse(count(&after, cmd)), Reverse(count(&before, cmd))));
+
+	let mut output = String::new();
+	if commands.is_empty() {
+		writeln!(output, "No commands were recorded in either period.").unwrap();
+		return output
+	}
+
+	let mut table = Table::new()
+		.with_n_columns(6)
+		.with_heading(0, "Command")
+		.with_heading(1, "A")
+		.with_heading(2, "B")
+		.with_heading(3, "Change")
+		.with_heading(4, "Percent")
+		.with_heading(5, "Rank")
+		.with_title(format!("{} {}", "Compare".with(Color::Cyan).bold(), format!("A: {a}  B: {b}").with(Color::DarkGrey)));
+	for cmd in commands.iter().take(num) {
+		let (n_a, n_b) = (count(&before, cmd), count(&after, cmd));
+		let delta = n_b as i64 - n_a as i64;
+		let tint = |cell: Cell| match delta.signum() {
+			1 => cell.with_color(Color::Green),
+			-1 => cell.with_color(Color::Red),
+			_ => cell
+		};
+		let change = if delta == 0 { "0".to_string() } else { format!("{delta:+}") };
+		let change = tint(Cell::new(change).with_sort_value(SortValue::Int(delta)));
+		let percent = match (n_a, n_b) {
+			(0, _) => Cell::new("new").with_sort_value(SortValue::Float(f64::INFINITY)).with_color(Color::Green),
+			(_, 0) => Cell::new("gone").with_sort_value(SortValue::Float(-100.0)).with_color(Color::Red),
+			_ if delta == 0 => Cell::new("0%").with_sort_value(SortValue::Float(0.0)),
+			_ => {
+				let pct = delta as f64 / n_a as f64 * 100.0;
+				tint(Cell::new(format!("{pct:+.01}%")).with_sort_value(SortValue::Float(pct)))
+			}
+		};
+		let rank = match (before.get(*cmd), after.get(*cmd)) {
+			(Some(&(_, ra)), Some(&(_, rb))) if ra > rb => Cell::new(format!("▲{}", ra - rb)).with_color(Color::Green),
+			(Some(&(_, ra)), Some(&(_, rb))) if ra < rb => Cell::new(format!("▼{}", rb - ra)).with_color(Color::Red),
+			(Some(_), Some(_)) => Cell::new("="),
+			(None, _) => Cell::new("new").with_color(Color::Green),
+			(_, None) => Cell::new("gone").with_color(Color::Red),
+		};
+		let row = Row::new()
+			.with_cell(Cell::new(cmd))
+			.with_cell(Cell::int(n_a))
+			.with_cell(Cell::int(n_b))
+			.with_cell(change)
+			.with_cell(percent)
+			.with_cell(rank);
+		table.add_row(row);
+	}
+	writeln!(output, "{table}").unwrap();
+
+	let adopted: Vec<&str> = commands.iter().filter(|cmd| !before.contains_key(**cmd)).map(|cmd| cmd.as_str()).collect();
+	let abandoned: Vec<&str> = commands.iter().filter(|cmd| !after.contains_key(**cmd)).map(|cmd| cmd.as_str()).collect();
+	if !adopted.is_empty() {
+		writeln!(output, "{}: {}", "newly adopted".with(Color::Green).bold(), adopted.join(", ")).unwrap();
+	}
+	if !abandoned.is_empty() {
+		writeln!(output, "{}: {}", "abandoned".with(Color::Red).bold(), abandoned.join(", ")).unwrap();
+	}
+	output
+}
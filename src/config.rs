@@ -0,0 +1,52 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use dirs::config_dir;
+use serde::{Deserialize, Deserializer};
+
+use crate::time::parse_duration;
+
+/// Default half-life used for the score column when none is configured
+pub const DEFAULT_HALF_LIFE: u64 = crate::time::DAY * 90;
+
+/// User configuration, read from `config.json`
+#[derive(Deserialize,Debug,Default)]
+#[serde(default)]
+pub struct Config {
+	/// Age at which an invocation counts for half as much in the score column
+	#[serde(deserialize_with = "de_duration")]
+	pub half_life: Option<u64>,
+	/// Events older than this are dropped from the history whenever cmdstat runs
+	#[serde(deserialize_with = "de_duration")]
+	pub retention: Option<u64>,
+	/// Equivalence groups displayed as a single command, e.g. `"vim": ["vim", "nvim", "vi"]`
+	pub groups: BTreeMap<String,Vec<String>>,
+	/// Categories of commands, e.g. `"vcs": ["git", "hg"]`
+	pub tags: BTreeMap<String,Vec<String>>,
+}
+
+fn de_duration<'de, D: Deserializer<'de>>(de: D) -> Result<Option<u64>, D::Error> {
+	let raw: Option<String> = Option::deserialize(de)?;
+	raw.map(|s| parse_duration(&s))
+		.transpose()
+		.map_err(serde::de::Error::custom)
+}
+
+pub fn config_file() -> PathBuf {
+	if let Ok(var) = env::var("CMDSTAT_CONFIG") {
+		var.into()
+	} else {
+		config_dir()
+			.unwrap()
+			.join("cmdstat")
+			.join("config.json")
+	}
+}
+
+/// Read the config file. A missing file gives the default config.
+pub fn read_config() -> Result<Config,String> {
+	let path = config_file();
+	let Ok(raw) = fs::read_to_string(&path) else {
+		return Ok(Config::default())
+	};
+	serde_json::from_str(&raw).map_err(|e| format!("cmdstat: failed to parse {}: {e}", path.display()))
+}
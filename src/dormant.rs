@@ -0,0 +1,61 @@
+use std::fmt::Write;
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{events::Events, table::{Cell, Row, Table}, time::{self, DAY, HOUR, MINUTE}, time_cell, Entries, Entry};
+
+/// Format the threshold exactly, in days at most, so that '90d' isn't shown rounded down to '12w'
+fn format_threshold(secs: u64) -> String {
+	let (scale, unit) = [(DAY, "d"), (HOUR, "h"), (MINUTE, "m")]
+		.into_iter()
+		.find(|&(scale, _)| secs > 0 && secs.is_multiple_of(scale))
+		.unwrap_or((1, "s"));
+	format!("{}{unit}", secs / scale)
+}
+
+/// Render a table of the commands with at least `min_count` calls that haven't been run for
+/// `older_than` seconds, most used first.
+///
+/// Commands missing from the event log only count as dormant once the log itself is older
+/// than `older_than`, since they would have shown up in it otherwise.
+pub fn format_dormant(entries: &Entries, events: &Events, older_than: u64, min_count: u32, num: usize, absolute: bool) -> String {
+	let now = time::now();
+	let seen = events.seen_times();
+	let log_span = events.0.iter().map(|ev| now.saturating_sub(ev.time)).max().unwrap_or(0);
+	let mut dormant: Vec<(&Entry, Option<u64>, u64)> = entries.0.iter()
+		.filter(|ent| ent.count >= min_count)
+		.map(|ent| match ent.seen(&seen) {
+			Some((_, last)) => (ent, Some(last), now.saturating_sub(last)),
+			None => (ent, None, log_span),
+		})
+		.filter(|&(_, _, idle)| idle >= older_than)
+		.collect();
+	dormant.sort_by(|a, b| b.0.count.cmp(&a.0.count).then_with(|| a.0.command.cmp(&b.0.command)));
+
+	let mut output = String::new();
+	if dormant.is_empty() {
+		writeln!(output, "Every command used at least {min_count} times has been run in the last {}.", format_threshold(older_than)).unwrap();
+		return output
+	}
+	let mut table = Table::new()
+		.with_n_columns(4)
+		.with_heading(0, "Command")
+		.with_heading(1, "Count")
+		.with_heading(2, "Last")
+		.with_heading(3, "Idle")
+		.with_title(format!("{} {}", "Dormant Commands".with(Color::Cyan).bold(), format!("(unused for {})", format_threshold(older_than)).with(Color::DarkGrey)));
+	for (entry, last, idle) in dormant.into_iter().take(num) {
+		let idle = match last {
+			Some(_) => time::format_duration(idle),
+			None => format!(">{}", time::format_duration(idle)),
+		};
+		let row = Row::new()
+			.with_cell(Cell::new(&entry.command))
+			.with_cell(Cell::int(entry.count))
+			.with_cell(time_cell(last, now, absolute))
+			.with_cell(Cell::new(idle));
+		table.add_row(row);
+	}
+	writeln!(output, "{table}").unwrap();
+	output
+}
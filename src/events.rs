@@ -0,0 +1,272 @@
+use std::{collections::HashMap, env, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{stats_file, CmdKind, Entries, Entry};
+
+/// A single recorded invocation, as appended to the event log by the zsh plugin
+#[derive(Serialize,Deserialize,Clone,PartialEq,Eq,Hash,Debug)]
+pub struct Event {
+	pub time: u64,
+	pub command: String,
+	#[serde(with = "crate::paths::portable")]
+	pub dir: PathBuf,
+	pub kind: CmdKind,
+	#[serde(default)]
+	pub host: String,
+	#[serde(default)]
+	pub user: String,
+	#[serde(default)]
+	pub tty: String,
+	#[serde(default)]
+	pub pid: u32,
+	#[serde(default)]
+	pub session: String,
+	#[serde(default)]
+	pub ssh: bool,
+	/// Root of the git repository the command was run in
+	#[serde(default, with = "crate::paths::portable_opt")]
+	pub repo: Option<PathBuf>,
+	/// The word following the command when it looks like a subcommand, like `add` in `git add`
+	#[serde(default)]
+	pub sub: Option<String>,
+}
+
+/// The ordered invocation history. Oldest events come first.
+#[derive(Debug,Default)]
+pub struct Events(pub Vec<Event>);
+
+impl Events {
+	/// Remove every event for which `predicate` returns true, returning the removed events
+	pub fn extract<F: FnMut(&Event) -> bool>(&mut self, mut predicate: F) -> Vec<Event> {
+		let (removed, kept) = std::mem::take(&mut self.0)
+			.into_iter()
+			.partition(|ev| predicate(ev));
+		self.0 = kept;
+		removed
+	}
+	/// Aggregate the events into per-command entries. Each entry takes the kind of its most recent event.
+	pub fn to_entries(&self) -> Entries {
+		let mut entries: Vec<Entry> = vec![];
+		let mut index: HashMap<&str,usize> = HashMap::new();
+		for event in &self.0 {
+			let idx = *index.entry(&event.command).or_insert_with(|| {
+				entries.push(Entry {
+					command: event.command.clone(),
+					count: 0,
+					kind: event.kind,
+					dirs: HashMap::new(),
+					hosts: HashMap::new(),
+					kinds: HashMap::new(),
+					members: vec![],
+					ranking: None,
+				});
+				entries.len() - 1
+			});
+			let entry = &mut entries[idx];
+			entry.count += 1;
+			entry.kind = event.kind;
+			*entry.dirs.entry(event.dir.clone()).or_default() += 1;
+			*entry.kinds.entry(event.kind).or_default() += 1;
+			if !event.host.is_empty() {
+				*entry.hosts.entry(event.host.clone()).or_default() += 1;
+			}
+		}
+		Entries(entries)
+	}
+	/// Each point where the recorded kind of any of `commands` changed, as (time, old kind, new kind)
+	pub fn kind_transitions(&self, commands: &[&str]) -> Vec<(u64, CmdKind, CmdKind)> {
+		let mut transitions = vec![];
+		let mut current: HashMap<&str,CmdKind> = HashMap::new();
+		for event in self.0.iter().filter(|ev| commands.contains(&ev.command.as_str())) {
+			if let Some(prev) = current.insert(&event.command, event.kind) && prev != event.kind {
+				transitions.push((event.time, prev, event.kind));
+			}
+		}
+		transitions
+	}
+	/// The first and last time each command appears
+	pub fn seen_times(&self) -> HashMap<String,(u64,u64)> {
+		let mut seen: HashMap<String,(u64,u64)> = HashMap::new();
+		for event in &self.0 {
+			seen.entry(event.command.clone())
+				.and_modify(|(first, last)| {
+					*first = (*first).min(event.time);
+					*last = (*last).max(event.time);
+				})
+				.or_insert((event.time, event.time));
+		}
+		seen
+	}
+	/// Remove the `n` most recent events, returning them
+	pub fn extract_last_n(&mut self, n: usize) -> Vec<Event> {
+		let split = self.0.len().saturating_sub(n);
+		self.0.split_off(split)
+	}
+	/// Remove one event equal to each of `events`, returning the ones that were found
+	pub fn extract_matching(&mut self, events: &[Event]) -> Vec<Event> {
+		let mut pending: HashMap<&Event,usize> = HashMap::new();
+		for event in events {
+			*pending.entry(event).or_default() += 1;
+		}
+		self.extract(|ev| match pending.get_mut(ev) {
+			Some(n) if *n > 0 => {
+				*n -= 1;
+				true
+			}
+			_ => false
+		})
+	}
+}
+
+impl Entry {
+	/// The first and last time this entry, or any command grouped into it, appears in `seen`
+	pub fn seen(&self, seen: &HashMap<String,(u64,u64)>) -> Option<(u64,u64)> {
+		self.member_names()
+			.into_iter()
+			.filter_map(|cmd| seen.get(cmd))
+			.fold(None, |acc, &(first, last)| match acc {
+				Some((f, l)) => Some((first.min(f), last.max(l))),
+				None => Some((first, last))
+			})
+	}
+}
+
+impl Entries {
+	/// Take back the counts contributed by `events`, dropping entries and dirs that reach zero
+	pub fn subtract_events(&mut self, events: &[Event]) {
+		for event in events {
+			let Some(entry) = self.0.iter_mut().find(|ent| ent.command == event.command) else {
+				continue
+			};
+			entry.count = entry.count.saturating_sub(1);
+			if let Some(n) = entry.dirs.get_mut(&event.dir) {
+				*n = n.saturating_sub(1);
+				if *n == 0 {
+					entry.dirs.remove(&event.dir);
+				}
+			}
+			if let Some(n) = entry.hosts.get_mut(&event.host) {
+				*n = n.saturating_sub(1);
+				if *n == 0 {
+					entry.hosts.remove(&event.host);
+				}
+			}
+			if let Some(n) = entry.kinds.get_mut(&event.kind) {
+				*n = n.saturating_sub(1);
+				if *n == 0 {
+					entry.kinds.remove(&event.kind);
+				}
+			}
+		}
+		self.retain_entries(|ent| ent.count > 0);
+	}
+	/// Exponentially decayed usage score for each command, where an invocation `half_life`
+	/// seconds old is worth half of a fresh one. Invocations that predate the event log are
+	/// treated as if they happened at the time of the oldest recorded event.
+	pub fn decay_scores(&self, events: &Events, half_life: u64, now: u64) -> HashMap<String,f64> {
+		let weight = |time: u64| 0.5f64.powf(now.saturating_sub(time) as f64 / half_life.max(1) as f64);
+		let oldest = events.0.iter().map(|ev| ev.time).min().unwrap_or(now);
+		let mut timed: HashMap<&str,(u32,f64)> = HashMap::new();
+		for event in &events.0 {
+			let (n, score) = timed.entry(&event.command).or_default();
+			*n += 1;
+			*score += weight(event.time);
+		}
+		self.0.iter()
+			.map(|ent| {
+				let (n, score) = ent.member_names()
+					.into_iter()
+					.filter_map(|cmd| timed.get(cmd))
+					.fold((0, 0.0), |(n, score), (m, s)| (n + m, score + s));
+				let untimed = ent.count.saturating_sub(n);
+				(ent.command.clone(), score + untimed as f64 * weight(oldest))
+			})
+			.collect()
+	}
+	/// Split the `window` seconds leading up to `now` into `n` equal buckets, and count each
+	/// command's calls in every bucket, oldest first
+	pub fn usage_buckets(&self, events: &Events, window: u64, n: usize, now: u64) -> HashMap<String,Vec<u32>> {
+		let start = now.saturating_sub(window);
+		let width = window.div_ceil(n as u64).max(1);
+		let mut timed: HashMap<&str,Vec<u32>> = HashMap::new();
+		for event in events.0.iter().filter(|ev| (start..now).contains(&ev.time)) {
+			let bucket = (((event.time - start) / width) as usize).min(n - 1);
+			timed.entry(&event.command).or_insert_with(|| vec![0; n])[bucket] += 1;
+		}
+		self.0.iter()
+			.map(|ent| {
+				let mut buckets = vec![0; n];
+				for counts in ent.member_names().into_iter().filter_map(|cmd| timed.get(cmd)) {
+					for (total, count) in buckets.iter_mut().zip(counts) {
+						*total += count;
+					}
+				}
+				(ent.command.clone(), buckets)
+			})
+			.collect()
+	}
+}
+
+pub fn events_file() -> PathBuf {
+	if let Ok(var) = env::var("CMDSTAT_EVENTS") {
+		var.into()
+	} else {
+		stats_file().with_file_name("events.jsonl")
+	}
+}
+
+/// Read the event log. Lines that fail to parse are skipped, and left in place by `write_events`.
+pub fn read_events() -> Events {
+	let raw = fs::read_to_string(events_file()).unwrap_or_default();
+	let events = raw.lines()
+		.filter(|line| !line.trim().is_empty())
+		.filter_map(|line| serde_json::from_str(line).ok())
+		.collect();
+	Events(events)
+}
+
+/// Lines of a raw event log that don't parse as events, like a half written line or one from a newer version
+fn unparsed_lines(raw: &str) -> impl Iterator<Item = &str> {
+	raw.lines()
+		.filter(|line| !line.trim().is_empty())
+		.filter(|line| serde_json::from_str::<Event>(line).is_err())
+}
+
+/// Rewrite the event log with `events`. Lines of the current log that `read_events` skipped are
+/// kept as they are, ahead of the events, so that rewriting the log never loses them.
+pub fn write_events(events: &Events) -> io::Result<()> {
+	let current = fs::read_to_string(events_file()).unwrap_or_default();
+	let mut raw = String::new();
+	for line in unparsed_lines(&current) {
+		raw.push_str(line);
+		raw.push('\n');
+	}
+	for event in &events.0 {
+		raw.push_str(&serde_json::to_string(event)?);
+		raw.push('\n');
+	}
+	fs::write(events_file(), raw)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_the_lines_that_dont_parse() {
+		let raw = concat!(
+			"{\"time\":1,\"command\":\"git\",\"dir\":\"/tmp\",\"kind\":\"command\"}\n",
+			"\n",
+			"{\"time\":2,\"command\":\"ls\",\"dir\":\"/tmp\",\"kind\":\"builtin\",\"host\":\"a\"}\n",
+			"{\"time\":3,\"command\":\"vim\",\"dir\":\"/t\n",
+			"{\"time\":\"yesterday\",\"command\":\"git\",\"dir\":\"/tmp\",\"kind\":\"command\"}\n",
+			"{\"time\":4,\"command\":\"cd\",\"dir\":\"/tmp\",\"kind\":\"wand\",\"extra\":[1]}",
+		);
+		let unparsed: Vec<&str> = unparsed_lines(raw).collect();
+		assert_eq!(unparsed, [
+			"{\"time\":3,\"command\":\"vim\",\"dir\":\"/t",
+			"{\"time\":\"yesterday\",\"command\":\"git\",\"dir\":\"/tmp\",\"kind\":\"command\"}",
+		]);
+	}
+}
@@ -0,0 +1,76 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap}};
+
+use crate::{events::Events, Entries, Entry};
+
+impl Entries {
+	/// Combine the entries of each equivalence group into a single entry named after the group.
+	/// The combined entry takes the kind of its most used member, and keeps a per-member breakdown.
+	pub fn apply_groups(&mut self, groups: &BTreeMap<String,Vec<String>>) {
+		for (name, members) in groups {
+			let (grouped, rest): (Vec<Entry>, Vec<Entry>) = std::mem::take(&mut self.0)
+				.into_iter()
+				.partition(|ent| ent.command == *name || members.contains(&ent.command));
+			self.0 = rest;
+			let Some(kind) = grouped.iter().max_by_key(|ent| ent.count).map(|ent| ent.kind) else {
+				continue
+			};
+
+			let mut breakdown: Vec<(String,u32)> = grouped.iter()
+				.map(|ent| (ent.command.clone(), ent.count))
+				.collect();
+			breakdown.sort_by_key(|(cmd, n)| (Reverse(*n), cmd.clone()));
+			let mut group = Entry {
+				command: name.clone(),
+				count: 0,
+				kind,
+				dirs: HashMap::new(),
+				hosts: HashMap::new(),
+				kinds: HashMap::new(),
+				members: vec![],
+				ranking: None,
+			};
+			for entry in grouped {
+				group.absorb(entry);
+			}
+			group.members = breakdown;
+			self.0.push(group);
+		}
+	}
+	/// Merge the entry for `old` into the entry for `new`, renaming it if `new` doesn't exist yet.
+	/// Returns false if there is no entry for `old`.
+	pub fn rename_command(&mut self, old: &str, new: &str) -> bool {
+		let Some(idx) = self.0.iter().position(|ent| ent.command == old) else {
+			return false
+		};
+		let mut entry = self.0.remove(idx);
+		match self.0.iter_mut().find(|ent| ent.command == new) {
+			Some(existing) => existing.absorb(entry),
+			None => {
+				entry.command = new.to_string();
+				self.0.push(entry);
+			}
+		}
+		true
+	}
+}
+
+impl Entry {
+	/// The recorded command names this entry covers
+	pub fn member_names(&self) -> Vec<&str> {
+		if self.members.is_empty() {
+			vec![self.command.as_str()]
+		} else {
+			self.members.iter().map(|(cmd,_)| cmd.as_str()).collect()
+		}
+	}
+}
+
+impl Events {
+	pub fn rename_command(&mut self, old: &str, new: &str) {
+		for event in &mut self.0 {
+			if event.command == old {
+				event.command = new.to_string();
+			}
+		}
+	}
+}
@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{events::Events, time};
+
+const HEAT_CHARS: [&str;4] = [
+	"░",
+	"▒",
+	"▓",
+	"█",
+];
+
+/// Days in display order, as indices into `tm_wday` (which starts on Sunday)
+const WEEKDAYS: [(usize, &str);7] = [
+	(1, "Mon"),
+	(2, "Tue"),
+	(3, "Wed"),
+	(4, "Thu"),
+	(5, "Fri"),
+	(6, "Sat"),
+	(0, "Sun"),
+];
+
+/// Count calls by local day of the week and hour of the day. With a command given, only its calls
+/// count, along with those of its group members if it names an equivalence group.
+pub fn tally_hours(events: &Events, command: Option<&str>, groups: &BTreeMap<String,Vec<String>>) -> [[u32;24];7] {
+	let members = command.and_then(|cmd| groups.get(cmd));
+	let mut grid = [[0;24];7];
+	for event in &events.0 {
+		if let Some(cmd) = command && event.command != cmd && !members.is_some_and(|m| m.contains(&event.command)) {
+			continue
+		}
+		let tm = time::local_time(event.time);
+		grid[tm.tm_wday as usize][tm.tm_hour as usize] += 1;
+	}
+	grid
+}
+
+/// Render a week by hour grid of shaded blocks, darker where more calls were made.
+/// The grid should hold at least one call.
+pub fn format_heatmap(grid: &[[u32;24];7], title: &str, color: Option<Color>) -> String {
+	let color = color.unwrap_or(Color::Green);
+	let max = grid.iter().flatten().copied().max().unwrap_or(0);
+	let total: u32 = grid.iter().flatten().sum();
+	let mut output = String::new();
+	writeln!(output, "{} {}", title.with(Color::Cyan).bold(), format!("({total} calls)").with(Color::DarkGrey)).unwrap();
+	let mut hours = String::from("    ");
+	for hour in (0..24).step_by(3) {
+		write!(hours, "{hour:<6}").unwrap();
+	}
+	writeln!(output, "{}", hours.trim_end().with(Color::DarkGrey)).unwrap();
+	for (wday, name) in WEEKDAYS {
+		write!(output, "{name} ").unwrap();
+		for &n in &grid[wday] {
+			if n == 0 {
+				write!(output, "{}", "· ".with(Color::DarkGrey)).unwrap();
+			} else {
+				let level = ((n as f64 / max as f64) * HEAT_CHARS.len() as f64).ceil() as usize;
+				let shade = HEAT_CHARS[level.clamp(1, HEAT_CHARS.len()) - 1];
+				write!(output, "{}", shade.repeat(2).with(color)).unwrap();
+			}
+		}
+		writeln!(output).unwrap();
+	}
+
+	let (busiest_day, busiest_hour) = WEEKDAYS.iter()
+		.flat_map(|&(wday, name)| (0..24).map(move |hour| (wday, name, hour)))
+		.max_by_key(|&(wday, _, hour)| grid[wday][hour])
+		.map(|(_, name, hour)| (name, hour))
+		.unwrap();
+	writeln!(output).unwrap();
+	writeln!(output, "less {} more", HEAT_CHARS.concat().with(color)).unwrap();
+	writeln!(output, "busiest: {busiest_day} {busiest_hour:02}:00 ({max} calls)").unwrap();
+	output
+}
@@ -1,14 +1,44 @@
-use std::{cmp::Reverse, collections::HashMap, env, fmt::{Display, Write}, fs, io::Write as IoWrite, path::{Path, PathBuf}, process::Stdio, str::FromStr};
+use std::{cmp::Reverse, collections::HashMap, env, fmt::{Display, Write}, fs, io::{self, Write as IoWrite}, os::fd::AsRawFd, path::{Path, PathBuf}, process::Stdio, str::FromStr};
 use regex::Regex;
 
-use clap::{arg, command, Parser};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use compare::{format_compare, last_two_periods, parse_period, TimeRange};
+use config::{read_config, Config, DEFAULT_HALF_LIFE};
 use crossterm::{style::{Color, Stylize}, terminal};
 use dirs::data_local_dir;
-use serde::Deserialize;
+use events::{read_events, write_events, Event};
+use merge::{read_sources, MergeSource};
+use projects::format_projects;
+use query::{glob_to_regex, parse_regex, Expr, Field, QueryContext};
+use sections::{GroupBy, Section};
+use tags::format_tags;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use table::{Cell, Row, Table};
+use table::{Cell, Row, SortOrder, SortValue, Table};
 
+pub mod compare;
+pub mod config;
+pub mod dormant;
+pub mod events;
+pub mod groups;
+pub mod heatmap;
+pub mod merge;
+pub mod paths;
+pub mod projects;
+pub mod query;
+pub mod relocate;
+pub mod sections;
+pub mod summary;
 pub mod table;
+pub mod tags;
+pub mod time;
+pub mod transitions;
+pub mod typos;
+
+/// Number of buckets the trend column splits its window into
+const TREND_BUCKETS: usize = 12;
+
+const SPARK_CHARS: [char;8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
 const BAR_CHARS: [&str;8] = [
 	"▏",
@@ -22,7 +52,7 @@ const BAR_CHARS: [&str;8] = [
 ];
 
 #[derive(Parser,Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, args_conflicts_with_subcommands = true)]
 struct Cli {
 	/// Display statistics for specific commands. 
 	commands: Vec<String>,
@@ -39,6 +69,10 @@ struct Cli {
 	#[arg(short, long, default_value = "20", help = "Choose a specific number of commands to show.")]
 	num: usize,
 
+	/// Show commands until they account for this share of calls
+	#[arg(long, value_parser = parse_percent, conflicts_with = "all", long_help = "Show the busiest commands until together they account for this share of calls, e.g. '90%'. Ignores --num.")]
+	until_cumulative: Option<f64>,
+
 	/// Display extra info about each command
 	#[arg(short)]
 	long: bool,
@@ -49,17 +83,95 @@ struct Cli {
 		'count/calls',
 		'usage/bar',
 		'percent/pct/%',
-		'type'.")]
+		'type',
+		'score',
+		'trend',
+		'first' (time since first recorded),
+		'last' (time since last recorded),
+		'idle',
+		'rank',
+		'cumulative/cum' (running percentage of calls down the ranking).")]
 	columns: Vec<TableColumn>,
 
-	/// Specify which column to sort by
-	#[arg(long)]
-	sort: Option<TableColumn>,
+	/// Specify which columns to sort by
+	#[arg(long, value_delimiter = ',', long_help = "Specify which columns to sort by, e.g. 'type,-count,command'.
+		Later columns break ties left by earlier ones, and ties are always broken by command name.
+		Prefix a column with '-' to sort it in descending order, or '+' for ascending order.
+		Without a prefix, numbers and bars sort in descending order and text in ascending order.")]
+	sort: Vec<SortSpec>,
 
 	/// Reverse the sort
 	#[arg(long)]
 	reverse: bool,
 
+	/// Half-life used to decay the score column
+	#[arg(long, value_parser = time::parse_duration, long_help = "Age at which an invocation counts for half as much in the score column, e.g. '30d'. Overrides the config file. Defaults to 90d.")]
+	half_life: Option<u64>,
+
+	/// Span of time covered by the trend column
+	#[arg(long, value_parser = time::parse_duration, default_value = "12w", long_help = "Span of time covered by the trend column, split into 12 equal buckets, e.g. '90d'.")]
+	trend_window: u64,
+
+	/// Show first and last use as dates
+	#[arg(long, global = true, long_help = "Show the first and last columns as dates instead of relative times like '3d ago'.")]
+	absolute: bool,
+
+	/// Display statistics for commands matching a glob
+	#[arg(long = "match", value_parser = glob_to_regex, long_help = "Display statistics for commands matching a glob, e.g. 'git*' or 'docker-*'. Can be given multiple times.")]
+	globs: Vec<Regex>,
+
+	/// Display statistics for commands matching a regex
+	#[arg(long = "regex", value_parser = parse_regex, long_help = "Display statistics for commands matching a regex, e.g. '^(git|hg)$'. Can be given multiple times.")]
+	regexes: Vec<Regex>,
+
+	/// Only show commands matching an expression
+	#[arg(long = "where", value_parser = Expr::parse, long_help = "Only show commands matching an expression, e.g. 'count > 50 && kind == alias && dirs > 3'.
+		Fields:
+		'command', 'kind', 'count', 'percent', 'dirs' (number of directories), 'hosts' (number of hosts),
+		'age' (time since first recorded), 'idle' (time since last recorded).
+		Operators: == != < <= > >= =~ (regex), combined with && || ! and parentheses.
+		Durations like '30d' can be compared against 'age' and 'idle'.")]
+	filter: Option<Expr>,
+
+	/// Only show commands of these kinds
+	#[arg(long, value_delimiter = ',', long_help = "Only show commands of these kinds. Possible options are:
+		'alias', 'function', 'builtin', 'command', 'reserved', 'script', 'unknown'.")]
+	kind: Vec<CmdKind>,
+
+	/// Hide commands of these kinds
+	#[arg(long, value_delimiter = ',')]
+	exclude_kind: Vec<CmdKind>,
+
+	/// What percentages are relative to
+	#[arg(long, default_value = "shown", long_help = "What percentages are relative to. 'shown' uses the total of the commands being displayed, 'all' uses the total of every recorded command before any filtering.")]
+	percent_of: PercentOf,
+
+	/// Split the table into sections
+	#[arg(long, conflicts_with_all = ["total", "long"], long_help = "Split the table into a section for each kind, tag, host or project,
+		each with a subtotal and percentages relative to the section. Possible options are:
+		'kind', 'tag', 'host', 'project'.")]
+	group_by: Option<GroupBy>,
+
+	/// Only show commands carrying one of these tags
+	#[arg(long, value_delimiter = ',')]
+	tag: Vec<String>,
+
+	/// Only count invocations from this host
+	#[arg(long, long_help = "Only count invocations from this host. Works on merged stats files by narrowing the counts, while directories and kinds still reflect every host.")]
+	host: Option<String>,
+
+	/// Only count invocations from this shell session
+	#[arg(long, long_help = "Only count invocations from this shell session. 'current' selects the shell cmdstat was run from.")]
+	session: Option<String>,
+
+	/// Only count invocations made over SSH
+	#[arg(long, conflicts_with = "local")]
+	ssh: bool,
+
+	/// Only count invocations made outside of SSH
+	#[arg(long)]
+	local: bool,
+
 	/// Dump raw json
 	#[arg(long)]
 	json: bool,
@@ -76,23 +188,208 @@ struct Cli {
 		'dark_magenta'
 		'132,50,1'
 		'31'")]
+	#[arg(global = true)]
 	bar_color: Option<String>,
 
-	#[arg(long)]
+	#[arg(long, global = true)]
 	no_pager: bool,
 
 	#[arg(long)]
-	clear_stats: bool
+	clear_stats: bool,
+
+	#[command(subcommand)]
+	subcommand: Option<SubCommand>
 }
 
-#[derive(Clone,Copy,Debug)]
+impl Cli {
+	/// Whether any command names, globs or regexes were given to narrow the output
+	fn has_selectors(&self) -> bool {
+		!self.commands.is_empty() || !self.globs.is_empty() || !self.regexes.is_empty()
+	}
+	/// Whether `command` was asked for by name, glob or regex
+	fn selects(&self, command: &str) -> bool {
+		self.commands.iter().any(|cmd| cmd == command) ||
+		self.globs.iter().any(|glob| glob.is_match(command)) ||
+		self.regexes.iter().any(|regex| regex.is_match(command))
+	}
+}
+
+#[derive(Subcommand,Debug)]
+enum SubCommand {
+	/// Remove the most recent invocations from the event history
+	Undo {
+		/// Remove invocations recorded within this window, e.g. '10m' or '2h'
+		#[arg(long, value_parser = time::parse_duration, required_unless_present = "last_n", conflicts_with = "last_n")]
+		last: Option<u64>,
+
+		/// Remove the N most recent invocations
+		#[arg(long)]
+		last_n: Option<usize>,
+
+		/// Skip the confirmation prompt
+		#[arg(short, long)]
+		yes: bool
+	},
+	/// Combine several stats files, tagging each one's counts with a host name
+	Merge {
+		/// Stats files to merge, as FILE or FILE=HOST. The host defaults to the file name without its extension.
+		#[arg(required = true)]
+		sources: Vec<MergeSource>,
+
+		/// Write the merged stats to this file instead of stdout
+		#[arg(short, long)]
+		output: Option<PathBuf>
+	},
+	/// List the top commands used in each git repository
+	Projects {
+		/// Number of commands to show per repository
+		#[arg(short, long, default_value = "5")]
+		num: usize
+	},
+	/// Show how calls are spread across the tags defined in the config file
+	Tags,
+	/// Rank the command names that most often failed to resolve, with the closest known command
+	Typos {
+		/// Number of typos to show
+		#[arg(short, long, default_value = "20")]
+		num: usize,
+
+		/// Write an alias from each typo to its suggested command into this file, ready to be sourced
+		#[arg(long)]
+		aliases: Option<PathBuf>
+	},
+	/// Permanently merge the stats for one command into another
+	Rename {
+		old: String,
+		new: String
+	},
+	/// Compare how often each command was called in two periods
+	Compare {
+		/// Compare the latest period of this length with the one before it, e.g. 'week', 'month' or '10d'
+		#[arg(long, value_parser = parse_period, default_value = "week", conflicts_with_all = ["a", "b"])]
+		period: u64,
+
+		/// First range to compare, as START..END. Each end is a YYYY-MM-DD date or a duration ago like '30d'.
+		#[arg(long, requires = "b")]
+		a: Option<TimeRange>,
+
+		/// Second range to compare, in the same format as --a
+		#[arg(long, requires = "a")]
+		b: Option<TimeRange>,
+
+		/// Number of commands to show
+		#[arg(short, long, default_value = "20")]
+		num: usize
+	},
+	/// List heavily used commands that haven't been run in a while
+	Dormant {
+		/// How long a command has to go unused, e.g. '90d'
+		#[arg(long, value_parser = time::parse_duration, default_value = "90d")]
+		older_than: u64,
+
+		/// Only list commands called at least this many times
+		#[arg(long, default_value = "10")]
+		min_count: u32,
+
+		/// Number of commands to show
+		#[arg(short, long, default_value = "20")]
+		num: usize
+	},
+	/// Describe the whole stats file: totals, spread of usage across commands and kinds
+	Summary {
+		/// Print the summary as json
+		#[arg(long)]
+		json: bool
+	},
+	/// Rank the commands run straight after a command within the same shell session
+	Next {
+		/// A command, optionally followed by a subcommand, e.g. 'git add'
+		#[arg(required = true, num_args = 1..=2)]
+		command: Vec<String>,
+
+		/// Number of commands to show
+		#[arg(short, long, default_value = "10")]
+		num: usize
+	},
+	/// Rank the commands run straight before a command within the same shell session
+	Prev {
+		/// A command, optionally followed by a subcommand, e.g. 'git push'
+		#[arg(required = true, num_args = 1..=2)]
+		command: Vec<String>,
+
+		/// Number of commands to show
+		#[arg(short, long, default_value = "10")]
+		num: usize
+	},
+	/// Show which hours of the week commands are run at
+	Heatmap {
+		/// Only count calls to this command or group
+		command: Option<String>
+	},
+	/// Rewrite or clean up the directories recorded for each command
+	Dirs {
+		#[command(subcommand)]
+		action: DirsAction
+	}
+}
+
+#[derive(Subcommand,Debug)]
+enum DirsAction {
+	/// Move every recorded directory under OLD to live under NEW
+	Mv {
+		old: PathBuf,
+		new: PathBuf
+	},
+	/// List recorded directories that no longer exist on disk
+	Prune {
+		/// Remove the vanished directories from the stats file
+		#[arg(long, conflicts_with = "merge")]
+		remove: bool,
+
+		/// Fold the counts of vanished directories into their nearest existing parent
+		#[arg(long)]
+		merge: bool
+	}
+}
+
+const DEFAULT_COLUMNS: [TableColumn;4] = [TableColumn::Command, TableColumn::Count, TableColumn::Percent, TableColumn::Usage];
+
+#[derive(Clone,Copy,PartialEq,Debug)]
 pub enum TableColumn {
 	Command,
 	Count,
 	Usage,
 	Percent,
 	Dirs,
-	Type
+	Type,
+	Score,
+	Trend,
+	First,
+	Last,
+	Idle,
+	Rank,
+	Cumulative
+}
+
+/// A column to sort by, with an optional direction
+#[derive(Clone,Copy,Debug)]
+pub struct SortSpec {
+	column: TableColumn,
+	order: SortOrder
+}
+
+impl FromStr for SortSpec {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (order, column) = if let Some(column) = s.strip_prefix('-') {
+			(SortOrder::Descending, column)
+		} else if let Some(column) = s.strip_prefix('+') {
+			(SortOrder::Ascending, column)
+		} else {
+			(SortOrder::Natural, s)
+		};
+		Ok(Self { column: column.parse()?, order })
+	}
 }
 
 impl FromStr for TableColumn {
@@ -104,6 +401,13 @@ impl FromStr for TableColumn {
 			"usage" | "bar" => Ok(TableColumn::Usage),
 			"percent" | "pct" | "%" => Ok(TableColumn::Percent),
 			"type" => Ok(TableColumn::Type),
+			"score" => Ok(TableColumn::Score),
+			"trend" | "spark" => Ok(TableColumn::Trend),
+			"first" | "first-seen" => Ok(TableColumn::First),
+			"last" | "last-seen" => Ok(TableColumn::Last),
+			"idle" => Ok(TableColumn::Idle),
+			"rank" | "#" => Ok(TableColumn::Rank),
+			"cumulative" | "cum" => Ok(TableColumn::Cumulative),
 			_ => Err(format!("cmdstat: invalid column name `{}'", s))
 		}
 	}
@@ -118,11 +422,35 @@ impl Display for TableColumn {
 			TableColumn::Percent => write!(f,"Percent"),
 			TableColumn::Dirs => write!(f,"Dirs"),
 			TableColumn::Type => write!(f,"Type"),
+			TableColumn::Score => write!(f,"Score"),
+			TableColumn::Trend => write!(f,"Trend"),
+			TableColumn::First => write!(f,"First"),
+			TableColumn::Last => write!(f,"Last"),
+			TableColumn::Idle => write!(f,"Idle"),
+			TableColumn::Rank => write!(f,"Rank"),
+			TableColumn::Cumulative => write!(f,"Cumulative"),
+		}
+	}
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum PercentOf {
+	Shown,
+	All
+}
+
+impl FromStr for PercentOf {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"shown" => Ok(PercentOf::Shown),
+			"all" => Ok(PercentOf::All),
+			_ => Err(format!("cmdstat: invalid percentage base `{}', expected 'shown' or 'all'", s))
 		}
 	}
 }
 
-#[derive(Deserialize,Debug)]
+#[derive(Serialize,Deserialize,Clone,Copy,PartialEq,Eq,Hash,Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum CmdKind {
 	Alias,
@@ -130,10 +458,27 @@ pub enum CmdKind {
 	Builtin,
 	Command,
 	Reserved,
+	Script,
 	#[serde(other)]
 	Unknown
 }
 
+impl FromStr for CmdKind {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"alias" => Ok(CmdKind::Alias),
+			"function" => Ok(CmdKind::Function),
+			"builtin" => Ok(CmdKind::Builtin),
+			"command" => Ok(CmdKind::Command),
+			"reserved" => Ok(CmdKind::Reserved),
+			"script" => Ok(CmdKind::Script),
+			"unknown" => Ok(CmdKind::Unknown),
+			_ => Err(format!("cmdstat: invalid kind `{}'", s))
+		}
+	}
+}
+
 impl Display for CmdKind {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
@@ -142,28 +487,50 @@ impl Display for CmdKind {
 			CmdKind::Builtin => write!(f,"builtin"),
 			CmdKind::Command => write!(f,"command"),
 			CmdKind::Reserved => write!(f,"reserved"),
+			CmdKind::Script => write!(f,"script"),
 			CmdKind::Unknown => write!(f,"unknown"),
 		}
 	}
 }
 
-#[derive(Deserialize,Debug)]
+#[derive(Serialize,Deserialize,Clone,Debug)]
 pub struct Entry {
 	command: String,
 	count: u32,
 	kind: CmdKind,
+	#[serde(with = "paths::portable_dirs")]
 	dirs: HashMap<PathBuf,u32>,
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	hosts: HashMap<String,u32>,
+	/// How many calls were made while the command resolved to each kind
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	kinds: HashMap<CmdKind,u32>,
+	/// Per-command breakdown when this entry stands for an equivalence group
+	#[serde(skip)]
+	members: Vec<(String,u32)>,
+	/// Position by count among the entries being listed, with the calls made up to and including it
+	#[serde(skip)]
+	ranking: Option<(usize,u64)>,
 }
 
 impl Entry {
-	fn detail_display(&self) -> String {
+	fn detail_display(&self, events: &events::Events) -> String {
 		let mut display = String::new();
-		let Entry { command, count, kind, dirs } = self;
+		let transitions = events.kind_transitions(&self.member_names());
+		let Entry { command, count, kind, dirs, hosts, kinds, members, .. } = self;
 		let mut dirs: Vec<(PathBuf, u32)> = dirs.iter()
 			.map(|(p,n)| (p.clone(),*n))
 			.collect();
 		dirs.sort_by_key(|&(_,n)| Reverse(n));
 		dirs.truncate(10);
+		let mut hosts: Vec<(&String, u32)> = hosts.iter()
+			.map(|(h,n)| (h,*n))
+			.collect();
+		hosts.sort_by_key(|&(_,n)| Reverse(n));
+		let mut kinds: Vec<(CmdKind, u32)> = kinds.iter()
+			.map(|(k,n)| (*k,*n))
+			.collect();
+		kinds.sort_by_key(|&(_,n)| Reverse(n));
 
 		let calls = "calls".with(Color::Cyan).bold();
 		let class = "class".with(Color::Cyan).bold();
@@ -177,85 +544,193 @@ impl Entry {
 		writeln!(display).unwrap();
 		writeln!(display, "{calls}: {count}").unwrap();
 		writeln!(display, "{class}: {kind}").unwrap();
+		if kinds.len() > 1 {
+			let history = "kind history".with(Color::Cyan).bold();
+			writeln!(display, "{history}: ").unwrap();
+			for (kind,count) in kinds {
+				writeln!(display, "\t{}: {count}", kind.to_string().with(Color::Blue)).unwrap()
+			}
+		}
+		if !transitions.is_empty() {
+			let changes = "kind changes".with(Color::Cyan).bold();
+			writeln!(display, "{changes}: ").unwrap();
+			for (time, from, to) in transitions {
+				writeln!(display, "\t{}: {from} -> {}", time::format_date(time), to.to_string().with(Color::Blue)).unwrap()
+			}
+		}
+		if !members.is_empty() {
+			let grouped = "grouped".with(Color::Cyan).bold();
+			writeln!(display, "{grouped}: ").unwrap();
+			for (member,count) in members {
+				writeln!(display, "\t{}: {count}", member.clone().with(Color::Blue)).unwrap()
+			}
+		}
 		writeln!(display, "{top_dirs}: ").unwrap();
 		for (dir,count) in dirs {
 			let fmt_dir = prettify_dir(dir);
 			writeln!(display, "\t{fmt_dir}: {count}").unwrap()
 		}
+		if !hosts.is_empty() {
+			let host_title = "hosts".with(Color::Cyan).bold();
+			writeln!(display, "{host_title}: ").unwrap();
+			for (host,count) in hosts {
+				writeln!(display, "\t{}: {count}", host.clone().with(Color::Blue)).unwrap()
+			}
+		}
 
 		display
 	}
 }
 
-fn prettify_dir<P: AsRef<Path>>(dir: P) -> String {
-	let path = dir.as_ref();
-	let raw = path.display().to_string();
-	let is_home = if let Ok(home) = env::var("HOME") { raw.starts_with(&home) } else { false };
-	if is_home {
-		let home_dir_segs = PathBuf::from(env::var("HOME").unwrap()).components().count();
-		let path_segs = path.components().skip(home_dir_segs);
-		let mut pretty = "~".with(Color::Blue).to_string();
-		for seg in path_segs {
-			let slash = "/".with(Color::DarkCyan);
-			let seg = seg.as_os_str().to_string_lossy();
-			let seg_pretty = seg.with(Color::Blue);
-			pretty.push_str(&format!("{slash}{seg_pretty}"));
-		}
-		pretty
-	} else {
-		let path_segs = path.components().skip(1);
-		let mut pretty = "/".with(Color::DarkCyan).to_string();
-		for seg in path_segs {
-			let slash = "/".with(Color::DarkCyan);
-			let seg = seg.as_os_str().to_string_lossy();
-			let seg_pretty = seg.with(Color::Blue);
-			pretty.push_str(&format!("{slash}{seg_pretty}"));
-		}
-		pretty
-	}
+pub fn prettify_dir<P: AsRef<Path>>(dir: P) -> String {
+	let portable = paths::to_portable(dir.as_ref());
+	let raw = portable.to_string_lossy();
+	let slash = "/".with(Color::DarkCyan).to_string();
+	raw.split('/')
+		.map(|seg| if seg.is_empty() { String::new() } else { seg.with(Color::Blue).to_string() })
+		.collect::<Vec<_>>()
+		.join(&slash)
 }
 
-#[derive(Deserialize,Debug,Default)]
+#[derive(Serialize,Deserialize,Debug,Default)]
 pub struct Entries(Vec<Entry>);
 
+/// Where to stop listing entries
+#[derive(Clone,Copy,Debug)]
+pub enum Cutoff {
+	/// Keep this many entries
+	Count(usize),
+	/// Keep entries until their calls add up to `percent` of `total`
+	Cumulative { percent: f64, total: u64 },
+}
+
 impl Entries {
 	pub fn sort_entries(&mut self) {
-		self.0.sort_by(|ent_a, ent_b| ent_b.count.cmp(&ent_a.count));
+		self.0.sort_by(|ent_a, ent_b| ent_b.count.cmp(&ent_a.count).then_with(|| ent_a.command.cmp(&ent_b.command)));
 	}
-	pub fn prune_entries(&mut self, num: usize) {
-		self.0.truncate(num);
+	/// Drop the entries past `cutoff`. Entries should be sorted first.
+	pub fn prune_entries(&mut self, cutoff: Cutoff) {
+		match cutoff {
+			Cutoff::Count(num) => self.0.truncate(num),
+			Cutoff::Cumulative { percent, total } => {
+				let mut running = 0;
+				let keep = self.0.iter()
+					.take_while(|ent| {
+						let reached = total == 0 || running as f64 / total as f64 * 100.0 >= percent;
+						running += ent.count as u64;
+						!reached
+					})
+					.count();
+				self.0.truncate(keep);
+			}
+		}
 	}
 	pub fn retain_entries<F: FnMut(&Entry) -> bool>(&mut self, predicate: F) {
 		self.0.retain(predicate)
 	}
+	pub fn total(&self) -> u64 {
+		self.0.iter().map(|ent| ent.count as u64).sum()
+	}
+	/// Narrow each entry's count down to the calls attributed to `host`. Dirs and kinds aren't
+	/// recorded per host, so they still cover every host.
+	pub fn restrict_to_host(&mut self, host: &str) {
+		self.0.retain_mut(|ent| {
+			let Some(n) = ent.hosts.get(host).copied() else {
+				return false
+			};
+			ent.count = n;
+			ent.hosts.retain(|h,_| h == host);
+			true
+		});
+	}
 }
 
 #[derive(Debug)]
 pub struct CmdStats {
 	entries: Entries,
+	scores: HashMap<String,f64>,
+	/// Calls per trend bucket, oldest first
+	trends: HashMap<String,Vec<u32>>,
+	/// First and last time each command appears in the event log
+	seen: HashMap<String,(u64,u64)>,
+	now: u64,
+	/// Total calls across every recorded command, before any filtering
+	grand_total: u64,
 	cli: Cli
 }
 
 impl CmdStats {
-	pub fn prepare_entries(&mut self) {
-		self.entries.sort_entries();
-		if !self.cli.all {
-			self.entries.prune_entries(self.cli.num);
+	fn new(entries: Entries, grand_total: u64, cli: Cli, config: &Config) -> Self {
+		let wants = |column| cli.sort.iter().any(|spec| spec.column == column) || cli.columns.contains(&column);
+		let wants_events = [TableColumn::Score, TableColumn::Trend, TableColumn::First, TableColumn::Last, TableColumn::Idle]
+			.into_iter()
+			.any(wants);
+		let events = if wants_events { read_events() } else { events::Events::default() };
+		let now = time::now();
+		let scores = if wants(TableColumn::Score) {
+			let half_life = cli.half_life.or(config.half_life).unwrap_or(DEFAULT_HALF_LIFE);
+			entries.decay_scores(&events, half_life, now)
+		} else {
+			HashMap::new()
+		};
+		let trends = if wants(TableColumn::Trend) {
+			entries.usage_buckets(&events, cli.trend_window, TREND_BUCKETS, now)
+		} else {
+			HashMap::new()
+		};
+		let seen = events.seen_times();
+		Self { entries, scores, trends, seen, now, grand_total, cli }
+	}
+	pub fn score(&self, command: &str) -> f64 {
+		self.scores.get(command).copied().unwrap_or_default()
+	}
+	/// The total that percentages are relative to, given the entries being shown
+	pub fn percent_base(&self, shown: &Entries) -> u64 {
+		match self.cli.percent_of {
+			PercentOf::Shown => shown.total(),
+			PercentOf::All => self.grand_total,
+		}
+	}
+	/// Rank `entries` by count, sort them and cut them down to the requested number, or to the requested share of `total`
+	pub fn prepare_entries(&self, entries: &mut Entries, total: u64) {
+		entries.sort_entries();
+		let mut cumulative = 0;
+		for (i, entry) in entries.0.iter_mut().enumerate() {
+			cumulative += entry.count as u64;
+			entry.ranking = Some((i + 1, cumulative));
+		}
+		if self.cli.sort.first().is_some_and(|spec| spec.column == TableColumn::Score) {
+			let score = |ent: &Entry| self.score(&ent.command);
+			entries.0.sort_by(|a, b| score(b).total_cmp(&score(a)).then_with(|| a.command.cmp(&b.command)));
+		}
+		if let Some(percent) = self.cli.until_cumulative {
+			entries.prune_entries(Cutoff::Cumulative { percent, total });
+		} else if !self.cli.all {
+			entries.prune_entries(Cutoff::Count(self.cli.num));
 		}
 	}
-	pub fn get_entry_table(&self, bar_color: Option<Color>) -> Table {
+	/// Build the table for `entries`, with percentages relative to `total` and the cumulative
+	/// column relative to `ranked_total`, the total before the entries were cut down
+	pub fn get_entry_table(&self, entries: &Entries, total: u64, ranked_total: u64, bar_color: Option<Color>) -> Table {
 		let mut table = if !self.cli.columns.is_empty() {
-			self.get_specified_table(bar_color)
+			self.get_specified_table(entries, total, ranked_total, bar_color)
 		} else {
-			self.get_default_table(bar_color)
+			self.get_default_table(entries, total, bar_color)
 		};
-		if let Some(col) = &self.cli.sort {
-			let col_idx = table.find_col_idx(col).unwrap(); // TODO: handle this unwrap
-			table.set_sort_column(col_idx);
-		} else {
+		if self.cli.sort.is_empty() {
 			let col_idx = table.find_col_idx(TableColumn::Count).unwrap_or(0);
 			table.set_sort_column(col_idx);
 		}
+		for SortSpec { column, order } in &self.cli.sort {
+			let Some(col_idx) = table.find_col_idx(column) else {
+				eprintln!("cmdstat: can't sort by `{column}' since it isn't one of the displayed columns");
+				std::process::exit(1);
+			};
+			table.add_sort_key(col_idx, *order);
+		}
+		if let Some(col_idx) = table.find_col_idx(TableColumn::Command) {
+			table.add_sort_key(col_idx, SortOrder::Ascending);
+		}
 		if self.cli.reverse {
 			table.reverse();
 		}
@@ -263,9 +738,8 @@ impl CmdStats {
 		table.omit_header(self.cli.no_header);
 		table
 	}
-	pub fn get_specified_table(&self, bar_color: Option<Color>) -> Table {
+	pub fn get_specified_table(&self, entries: &Entries, total: u64, ranked_total: u64, bar_color: Option<Color>) -> Table {
 		let bar_color = bar_color.unwrap_or(Color::Green);
-		let total: usize = self.entries.0.iter().map(|ent| ent.count as usize).sum();
 		let columns = &self.cli.columns;
 		let mut table = Table::new()
 			.with_n_columns(columns.len());
@@ -275,9 +749,10 @@ impl CmdStats {
 			table = table.with_heading(i, column);
 		}
 
-		for entry in &self.entries.0 {
-			let Entry { command, count, kind, dirs: _ } = entry;
-			let percentage = ((*count as f64 / total as f64) * 100.0) as usize;
+		for entry in &entries.0 {
+			let Entry { command, count, kind, .. } = entry;
+			let percentage = (*count as f64 / total as f64) * 100.0;
+			let (rank, cumulative) = entry.ranking.unwrap_or_default();
 			let mut row = Row::new();
 			for column in columns {
 				match column {
@@ -285,17 +760,51 @@ impl CmdStats {
 						row = row.with_cell(Cell::new(command))
 					}
 					TableColumn::Count => {
-						row = row.with_cell(Cell::new(count))
+						row = row.with_cell(Cell::int(*count))
 					}
 					TableColumn::Usage => {
-						row = row.with_cell(Cell::new(get_bar(percentage, bar_width())).with_color(bar_color));
+						let bar = get_bar(percentage as usize, bar_width());
+						row = row.with_cell(Cell::new(bar).with_sort_value(SortValue::Float(percentage)).with_color(bar_color));
 					}
 					TableColumn::Percent => {
-						row = row.with_cell(Cell::new(format!("{percentage}%")));
+						let cell = Cell::new(format!("{}%", percentage as usize)).with_sort_value(SortValue::Float(percentage));
+						row = row.with_cell(cell);
 					}
 					TableColumn::Dirs => todo!(),
 					TableColumn::Type => {
-						row = row.with_cell(Cell::new(kind))
+						let cell = Cell::new(kind);
+						row = row.with_cell(if *kind == CmdKind::Script { cell.with_color(Color::Magenta) } else { cell })
+					}
+					TableColumn::Score => {
+						let score = self.score(command);
+						row = row.with_cell(Cell::new(format!("{score:.1}")).with_sort_value(SortValue::Float(score)))
+					}
+					TableColumn::Trend => {
+						let buckets = self.trends.get(command).map(Vec::as_slice).unwrap_or_default();
+						let cell = Cell::new(get_sparkline(buckets)).with_sort_value(SortValue::Float(trend_slope(buckets)));
+						row = row.with_cell(cell.with_color(bar_color))
+					}
+					TableColumn::First |
+					TableColumn::Last => {
+						let seen = entry.seen(&self.seen);
+						let time = seen.map(|(first, last)| if *column == TableColumn::First { first } else { last });
+						row = row.with_cell(time_cell(time, self.now, self.cli.absolute))
+					}
+					TableColumn::Idle => {
+						row = row.with_cell(match entry.seen(&self.seen) {
+							Some((_, last)) => {
+								let idle = self.now.saturating_sub(last);
+								Cell::new(time::format_duration(idle)).with_sort_value(SortValue::Duration(idle))
+							}
+							None => Cell::new("-").with_sort_value(SortValue::Duration(u64::MAX))
+						})
+					}
+					TableColumn::Rank => {
+						row = row.with_cell(Cell::new(rank).with_sort_value(SortValue::Rank(rank as u64)))
+					}
+					TableColumn::Cumulative => {
+						let share = (cumulative as f64 / ranked_total as f64) * 100.0;
+						row = row.with_cell(Cell::new(format!("{share:.01}%")).with_sort_value(SortValue::Float(share)))
 					}
 				}
 			}
@@ -304,9 +813,8 @@ impl CmdStats {
 
 		table
 	}
-	pub fn get_default_table(&self, bar_color: Option<Color>) -> Table {
+	pub fn get_default_table(&self, entries: &Entries, total: u64, bar_color: Option<Color>) -> Table {
 		let bar_color = bar_color.unwrap_or(Color::Green);
-		let total: usize = self.entries.0.iter().map(|ent| ent.count as usize).sum();
 		let mut table = Table::new()
 			.with_n_columns(4)
 			.with_heading(0, "Command")
@@ -314,13 +822,15 @@ impl CmdStats {
 			.with_heading(2, "Percent")
 			.with_heading(3, "Usage");
 
-		for entry in &self.entries.0 {
-			let Entry { command, count, kind: _, dirs: _ } = entry;
+		for entry in &entries.0 {
+			let Entry { command, count, .. } = entry;
 			let percentage = (*count as f64 / total as f64) * 100.0;
 			let cmd_cell = Cell::new(command);
-			let count_cell = Cell::new(count);
-			let bar_cell = Cell::new(get_bar(percentage as usize, bar_width())).with_color(bar_color);
-			let perc_cell = Cell::new(format!("{percentage:.01}%"));
+			let count_cell = Cell::int(*count);
+			let bar_cell = Cell::new(get_bar(percentage as usize, bar_width()))
+				.with_sort_value(SortValue::Float(percentage))
+				.with_color(bar_color);
+			let perc_cell = Cell::new(format!("{percentage:.01}%")).with_sort_value(SortValue::Float(percentage));
 
 			let row = Row::new()
 				.with_cell(cmd_cell)
@@ -332,18 +842,72 @@ impl CmdStats {
 
 		table
 	}
+	/// A footer row giving a section's total calls and its share of `total`
+	fn get_subtotal_row(&self, count: u64, total: u64, bar_color: Option<Color>) -> Row {
+		let bar_color = bar_color.unwrap_or(Color::Green);
+		let columns = if self.cli.columns.is_empty() { &DEFAULT_COLUMNS[..] } else { &self.cli.columns[..] };
+		let percentage = if total == 0 { 0.0 } else { (count as f64 / total as f64) * 100.0 };
+		let mut row = Row::new();
+		for column in columns {
+			let cell = match column {
+				TableColumn::Command => Cell::new("subtotal").with_color(Color::DarkGrey),
+				TableColumn::Count => Cell::int(count),
+				TableColumn::Percent if self.cli.columns.is_empty() => Cell::new(format!("{percentage:.01}%")),
+				TableColumn::Percent => Cell::new(format!("{}%", percentage as usize)),
+				TableColumn::Usage => Cell::new(get_bar(percentage as usize, bar_width())).with_color(bar_color),
+				TableColumn::Dirs |
+				TableColumn::Type |
+				TableColumn::Score |
+				TableColumn::Trend |
+				TableColumn::First |
+				TableColumn::Last |
+				TableColumn::Idle |
+				TableColumn::Rank |
+				TableColumn::Cumulative => Cell::new(""),
+			};
+			row = row.with_cell(cell);
+		}
+		row
+	}
 	pub fn format_entries(&mut self, bar_color: Option<Color>) -> String {
-		self.prepare_entries();
-		let table = self.get_entry_table(bar_color)
+		let mut entries = std::mem::take(&mut self.entries);
+		let ranked_total = self.percent_base(&entries);
+		self.prepare_entries(&mut entries, ranked_total);
+		let table = self.get_entry_table(&entries, self.percent_base(&entries), ranked_total, bar_color)
 			.with_title("Command Statistics".with(Color::Cyan).bold());
 		if !self.cli.no_header {
 			println!();
 		}
 		format!("{table}")
 	}
+	/// Render one table per section, each with a subtotal, followed by the grand total
+	pub fn format_sections(&self, sections: Vec<Section>, bar_color: Option<Color>) -> String {
+		let shown = self.entries.total();
+		let mut output = String::new();
+		if !self.cli.no_header {
+			writeln!(output).unwrap();
+		}
+		for Section { name, mut entries } in sections {
+			let subtotal = entries.total();
+			// Rows are relative to the whole section rather than to the rows left after the cut
+			let section_total = self.percent_base(&entries);
+			self.prepare_entries(&mut entries, section_total);
+			let mut table = self.get_entry_table(&entries, section_total, section_total, bar_color)
+				.with_title(name.with(Color::Cyan).bold());
+			if !self.cli.no_header {
+				table.set_footer(self.get_subtotal_row(subtotal, self.percent_base(&self.entries), bar_color));
+			}
+			writeln!(output, "{table}").unwrap();
+		}
+		if !self.cli.no_header {
+			let calls = if shown == 1 { "call" } else { "calls" };
+			writeln!(output, "{} {shown} {calls} across {} commands", "Total:".with(Color::Cyan).bold(), self.entries.0.len()).unwrap();
+		}
+		output
+	}
 }
 
-fn stats_file() -> PathBuf {
+pub fn stats_file() -> PathBuf {
 	if let Ok(var) = env::var("CMDSTAT_FILE") {
 		var.into()
 	} else {
@@ -367,6 +931,35 @@ fn read_stats() -> String {
 		.unwrap_or_default()
 }
 
+fn write_stats(entries: &Entries) -> io::Result<()> {
+	let raw = serde_json::to_string_pretty(entries)?;
+	fs::write(stats_file(), raw)
+}
+
+/// Holds the same flock that the zsh plugin takes before rewriting the stats file.
+/// The lock is released when this is dropped.
+pub struct StatsLock {
+	_file: fs::File
+}
+
+impl StatsLock {
+	pub fn acquire() -> io::Result<Self> {
+		let mut path = stats_file().into_os_string();
+		path.push(".lock");
+		let file = fs::OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+		if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+			return Err(io::Error::last_os_error())
+		}
+		Ok(Self { _file: file })
+	}
+	/// Take the lock, printing the reason if it can't be taken
+	pub fn acquire_or_report() -> Option<Self> {
+		Self::acquire()
+			.inspect_err(|e| eprintln!("Failed to lock stats file: {e}"))
+			.ok()
+	}
+}
+
 fn get_bar(percentage: usize, term_width: usize) -> String {
 	let scaled_percentage = (percentage as f64 / 100.0) * term_width as f64;
 	let full_bars = scaled_percentage.floor() as usize;
@@ -390,37 +983,93 @@ fn get_bar(percentage: usize, term_width: usize) -> String {
 	bar
 }
 
-fn clear_stats() {
-	use std::io::{self, Write};
-	use std::fs::{self, OpenOptions};
+/// Parse a percentage like '90%' or '90'
+fn parse_percent(s: &str) -> Result<f64,String> {
+	let num = s.trim().strip_suffix('%').unwrap_or(s.trim());
+	match num.parse::<f64>() {
+		Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+		_ => Err(format!("cmdstat: invalid percentage `{s}', expected a number between 0 and 100"))
+	}
+}
 
-	println!("This will irreversibly clear the stats file.");
-	let mut answer = String::new();
+/// A cell showing when something happened, either relative to `now` like '3d ago' or as a date.
+/// Times missing from the event log show as '-' and sort as the oldest.
+pub fn time_cell(time: Option<u64>, now: u64, absolute: bool) -> Cell {
+	match time {
+		Some(time) if absolute => Cell::new(time::format_date(time)),
+		Some(time) => Cell::new(format!("{} ago", time::format_duration(now.saturating_sub(time)))),
+		None => Cell::new("-"),
+	}.with_sort_value(SortValue::Timestamp(time.unwrap_or(0)))
+}
+
+/// Draw one block per bucket, scaled so the busiest bucket gets a full block. Empty buckets are left blank.
+fn get_sparkline(buckets: &[u32]) -> String {
+	let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+	buckets.iter()
+		.map(|&n| {
+			if n == 0 {
+				return ' '
+			}
+			let level = (n as f64 / max as f64 * SPARK_CHARS.len() as f64).ceil() as usize;
+			SPARK_CHARS[level.clamp(1, SPARK_CHARS.len()) - 1]
+		})
+		.collect()
+}
+
+/// Least squares slope of the buckets relative to their mean, so rising commands sort
+/// above fading ones regardless of how much they're used
+fn trend_slope(buckets: &[u32]) -> f64 {
+	let n = buckets.len() as f64;
+	let mean = buckets.iter().sum::<u32>() as f64 / n;
+	if buckets.len() < 2 || mean == 0.0 {
+		return 0.0
+	}
+	let mid = (n - 1.0) / 2.0;
+	let (cov, var) = buckets.iter()
+		.enumerate()
+		.fold((0.0, 0.0), |(cov, var), (i, &y)| {
+			let dx = i as f64 - mid;
+			(cov + dx * (y as f64 - mean), var + dx * dx)
+		});
+	cov / var / mean
+}
 
-	while answer.trim() != "y" && answer.trim() != "n" {
+fn confirm() -> bool {
+	loop {
+		let mut answer = String::new();
 		print!("Are you sure? y/n ");
-		io::stdout().flush().unwrap(); 
+		io::stdout().flush().unwrap();
 		if io::stdin().read_line(&mut answer).is_err() {
 			eprintln!("Failed to read input, exiting.");
-			return;
+			return false;
+		}
+		if answer.is_empty() {
+			return false; // EOF
 		}
 		match answer.trim() {
 			"n" => {
 				println!("Exiting.");
-				return;
+				return false;
 			}
-			"y" => break,
+			"y" => return true,
 			_ => continue,
 		}
 	}
+}
+
+fn clear_stats() {
+	use std::fs::OpenOptions;
+
+	println!("This will irreversibly clear the stats file.");
+	if !confirm() {
+		return;
+	}
 
 	let stats_path = stats_file();
 
-	if let Some(parent) = stats_path.parent() {
-		if let Err(e) = fs::create_dir_all(parent) {
-			eprintln!("Failed to create directory {}: {}", parent.display(), e);
-			return;
-		}
+	if let Some(parent) = stats_path.parent() && let Err(e) = fs::create_dir_all(parent) {
+		eprintln!("Failed to create directory {}: {}", parent.display(), e);
+		return;
 	}
 
 	match OpenOptions::new().write(true).truncate(true).create(true).open(&stats_path) {
@@ -437,6 +1086,318 @@ fn clear_stats() {
 	}
 }
 
+fn undo(last: Option<u64>, last_n: Option<usize>, yes: bool) {
+	let mut events = read_events();
+	let selected: Vec<Event> = if let Some(window) = last {
+		let cutoff = time::now().saturating_sub(window);
+		events.extract(|ev| ev.time >= cutoff)
+	} else {
+		events.extract_last_n(last_n.unwrap_or_default())
+	};
+	if selected.is_empty() {
+		println!("No recorded invocations to undo.");
+		return;
+	}
+
+	let mut per_cmd: Vec<(&str,usize)> = vec![];
+	for event in &selected {
+		match per_cmd.iter_mut().find(|(cmd,_)| *cmd == event.command) {
+			Some((_,n)) => *n += 1,
+			None => per_cmd.push((&event.command,1))
+		}
+	}
+	per_cmd.sort_by_key(|&(_,n)| Reverse(n));
+	println!("This will remove {} recorded invocations:", selected.len());
+	for (cmd,n) in &per_cmd {
+		println!("\t{}: {n}", cmd.with(Color::Cyan));
+	}
+	if !yes && !confirm() {
+		return;
+	}
+
+	// Other shells keep recording while the prompt is up, so the lock is only taken now and
+	// the removal applied to a fresh copy of the log
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let mut events = read_events();
+	let removed = events.extract_matching(&selected);
+	let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	entries.subtract_events(&removed);
+	if let Err(e) = write_stats(&entries) {
+		eprintln!("Failed to write to stats file: {e}");
+		return;
+	}
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+		return;
+	}
+	println!("Removed {} invocations.", removed.len());
+}
+
+/// Drop events older than the configured retention window from the event log. The counts in the
+/// stats file are left alone, so the all time totals stay intact.
+fn enforce_retention(retention: u64) {
+	let cutoff = time::now().saturating_sub(retention);
+	if !read_events().0.iter().any(|ev| ev.time < cutoff) {
+		return
+	}
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let mut events = read_events();
+	events.extract(|ev| ev.time < cutoff);
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+	}
+}
+
+fn merge_stats(sources: &[MergeSource], output: Option<&Path>) {
+	let sources = match read_sources(sources) {
+		Ok(sources) => sources,
+		Err(e) => {
+			eprintln!("{e}");
+			std::process::exit(1);
+		}
+	};
+	let n_files = sources.len();
+	let merged = Entries::merge(sources);
+	let raw = serde_json::to_string_pretty(&merged).unwrap();
+	if let Some(path) = output {
+		if let Err(e) = fs::write(path, raw) {
+			eprintln!("Failed to write to {}: {e}", path.display());
+			std::process::exit(1);
+		}
+		println!("Merged {} commands from {n_files} files into {}.", merged.0.len(), path.display());
+	} else {
+		println!("{raw}");
+	}
+}
+
+/// Apply the host, session and ssh filters.
+/// Session and ssh filters can only be answered by the event log, so entries are rebuilt from it when they are used.
+fn filter_by_origin(cli: &Cli, entries: Entries) -> Result<Entries,String> {
+	if cli.session.is_none() && !cli.ssh && !cli.local {
+		let mut entries = entries;
+		if let Some(host) = &cli.host {
+			entries.restrict_to_host(host);
+		}
+		return Ok(entries)
+	}
+	let session = match cli.session.as_deref() {
+		Some("current") => Some(env::var("CMDSTAT_SESSION")
+			.map_err(|_| "cmdstat: no current session found, is the zsh plugin loaded?".to_string())?),
+		Some(id) => Some(id.to_string()),
+		None => None
+	};
+	let mut events = read_events();
+	events.0.retain(|ev| {
+		cli.host.as_ref().is_none_or(|host| &ev.host == host) &&
+		session.as_ref().is_none_or(|id| &ev.session == id) &&
+		(!cli.ssh || ev.ssh) &&
+		(!cli.local || !ev.ssh)
+	});
+	Ok(events.to_entries())
+}
+
+/// Rewrite the stats file and event log so that paths under $HOME are stored as `~/...`
+fn migrate_legacy_paths() {
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	let events = read_events();
+	if let Err(e) = write_stats(&entries) {
+		eprintln!("Failed to write to stats file: {e}");
+		return;
+	}
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+	}
+}
+
+fn show_typos(num: usize, aliases: Option<&Path>, no_pager: bool) {
+	let misses = typos::read_misses();
+	if misses.is_empty() {
+		println!("No failed commands have been recorded.");
+		return;
+	}
+	let entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	let suggestions = typos::suggest(&misses, &entries);
+	if let Some(path) = aliases {
+		match typos::write_aliases(path, &suggestions) {
+			Ok(n) => println!("Wrote {n} aliases to {}.", path.display()),
+			Err(e) => {
+				eprintln!("Failed to write to {}: {e}", path.display());
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+	let shown = &suggestions[..num.min(suggestions.len())];
+	let table = typos::format_typos(shown);
+	handle_output(&format!("{table}"), no_pager);
+}
+
+fn show_heatmap(command: Option<&str>, cli: &Cli) {
+	let bar_color = bar_color_or_exit(cli);
+	let config = config_or_exit();
+	let grid = heatmap::tally_hours(&read_events(), command, &config.groups);
+	if grid.iter().flatten().all(|&n| n == 0) {
+		match command {
+			Some(cmd) => println!("No calls to `{cmd}' have been recorded in the event log."),
+			None => println!("No calls have been recorded in the event log yet."),
+		}
+		return
+	}
+	let title = match command {
+		Some(cmd) => format!("Activity for {cmd}"),
+		None => "Activity".to_string(),
+	};
+	handle_output(&heatmap::format_heatmap(&grid, &title, bar_color), cli.no_pager);
+}
+
+fn show_transitions(words: &[String], direction: transitions::Direction, num: usize, cli: &Cli) {
+	let bar_color = bar_color_or_exit(cli);
+	let config = config_or_exit();
+	let command = &words[0];
+	let pattern = transitions::Pattern {
+		command,
+		sub: words.get(1).map(String::as_str),
+		members: config.groups.get(command).map(Vec::as_slice).unwrap_or_default(),
+	};
+	let counts = transitions::transitions(&read_events(), &pattern, direction);
+	let output = transitions::format_transitions(&counts, &pattern, direction, num, bar_color);
+	handle_output(&output, cli.no_pager);
+}
+
+fn rename_command(old: &str, new: &str) {
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	if !entries.rename_command(old, new) {
+		eprintln!("cmdstat: no stats recorded for `{old}'");
+		std::process::exit(1);
+	}
+	let mut events = read_events();
+	events.rename_command(old, new);
+	if let Err(e) = write_stats(&entries) {
+		eprintln!("Failed to write to stats file: {e}");
+		return;
+	}
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+		return;
+	}
+	println!("Merged {} into {}.", old.with(Color::Cyan), new.with(Color::Cyan));
+}
+
+/// Resolve a directory given on the command line into the absolute form used in memory
+fn resolve_dir_arg(dir: &Path) -> PathBuf {
+	let dir = paths::expand(dir);
+	std::path::absolute(&dir).unwrap_or(dir)
+}
+
+fn move_dirs(old: &Path, new: &Path) {
+	let old = resolve_dir_arg(old);
+	let new = resolve_dir_arg(new);
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	let mut events = read_events();
+	let moved = entries.rename_dirs(&old, &new);
+	if moved == 0 {
+		println!("No recorded directories under {}.", prettify_dir(&old));
+		return;
+	}
+	events.rename_dirs(&old, &new);
+	if let Err(e) = write_stats(&entries) {
+		eprintln!("Failed to write to stats file: {e}");
+		return;
+	}
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+		return;
+	}
+	println!("Moved {moved} calls from {} to {}.", prettify_dir(&old), prettify_dir(&new));
+}
+
+fn prune_dirs(remove: bool, merge: bool) {
+	let Some(_lock) = StatsLock::acquire_or_report() else {
+		return
+	};
+	let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+	let host = relocate::local_host();
+	let unchecked = entries.unchecked_commands(&host);
+	if unchecked > 0 {
+		let commands = if unchecked == 1 { "command that was" } else { "commands that were" };
+		eprintln!("cmdstat: skipping the directories of {unchecked} {commands} also run on other hosts, since they can't be checked from here");
+	}
+	let vanished = entries.vanished_dirs(&host);
+	if vanished.is_empty() {
+		println!("Every recorded directory still exists.");
+		return;
+	}
+
+	let mut table = Table::new()
+		.with_n_columns(2)
+		.with_heading(0, "Directory")
+		.with_heading(1, "Calls")
+		.with_title("Vanished Directories".with(Color::Cyan).bold());
+	for (dir, n) in &vanished {
+		let dir = paths::to_portable(dir);
+		table.add_row(Row::new().with_cell(Cell::new(dir.display())).with_cell(Cell::int(*n)));
+	}
+	print!("{table}");
+	if !remove && !merge {
+		println!("Run with --remove to drop these directories, or --merge to fold them into their nearest existing parent.");
+		return;
+	}
+
+	entries.prune_dirs(merge, &host);
+	let mut events = read_events();
+	if merge {
+		events.merge_vanished_dirs(&host);
+	} else {
+		events.extract_vanished_dirs(&host);
+	}
+	if let Err(e) = write_stats(&entries) {
+		eprintln!("Failed to write to stats file: {e}");
+		return;
+	}
+	if let Err(e) = write_events(&events) {
+		eprintln!("Failed to write to event log: {e}");
+		return;
+	}
+	if merge {
+		println!("Merged {} directories into their parents.", vanished.len());
+	} else {
+		println!("Removed {} directories.", vanished.len());
+	}
+}
+
+/// The `--bar-color` given on the command line, exiting if it isn't a color
+fn bar_color_or_exit(cli: &Cli) -> Option<Color> {
+	cli.bar_color
+		.as_deref()
+		.map(get_color)
+		.transpose()
+		.unwrap_or_else(|e| {
+			eprintln!("{e}");
+			std::process::exit(1);
+		})
+}
+
+/// The config file, exiting if it can't be read
+fn config_or_exit() -> Config {
+	read_config().unwrap_or_else(|e| {
+		eprintln!("{e}");
+		std::process::exit(1);
+	})
+}
+
 fn get_color(color: &str) -> Result<Color,String> {
 	let color = color.to_ascii_lowercase();
 	let rgb_regex = Regex::new(r"^(?P<r>\d{1,3}),(?P<g>\d{1,3}),(?P<b>\d{1,3})$").unwrap();
@@ -510,19 +1471,134 @@ fn handle_output(output: &str, no_pager: bool) {
 	}
 }
 
+/// Parse the arguments, pointing out the `--` escape when a command name collides with a subcommand
+fn parse_cli() -> Cli {
+	let args: Vec<String> = env::args().collect();
+	let subcommands: Vec<String> = Cli::command()
+		.get_subcommands()
+		.map(|sub| sub.get_name().to_string())
+		.collect();
+	let cli = Cli::try_parse_from(&args).unwrap_or_else(|e| {
+		if matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) {
+			e.exit()
+		}
+		let _ = e.print();
+		let collided = matches!(e.kind(), ErrorKind::MissingRequiredArgument | ErrorKind::MissingSubcommand |
+			ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand | ErrorKind::UnknownArgument);
+		if let Some(name) = args.get(1).filter(|arg| collided && subcommands.contains(arg)) {
+			eprintln!("cmdstat: to show stats for the `{name}' command instead, run `cmdstat -- {name}'");
+		}
+		std::process::exit(e.exit_code());
+	});
+	if !args.iter().any(|arg| arg == "--") {
+		for name in cli.commands.iter().filter(|cmd| subcommands.contains(cmd)) {
+			eprintln!("cmdstat: showing stats for the `{name}' command. Subcommands have to come before any options, \
+				and `cmdstat -- {name}' silences this warning");
+		}
+	}
+	cli
+}
+
 fn main() {
-	let cli = Cli::parse();
+	let cli = parse_cli();
+	match &cli.subcommand {
+		Some(SubCommand::Undo { last, last_n, yes }) => {
+			undo(*last, *last_n, *yes);
+			return
+		}
+		Some(SubCommand::Merge { sources, output }) => {
+			merge_stats(sources, output.as_deref());
+			return
+		}
+		Some(SubCommand::Tags) => {
+			let bar_color = bar_color_or_exit(&cli);
+			let config = config_or_exit();
+			if config.tags.is_empty() {
+				eprintln!("cmdstat: no tags are defined in {}", config::config_file().display());
+				std::process::exit(1);
+			}
+			let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+			entries.apply_groups(&config.groups);
+			let table = format_tags(&entries, &config.tags, bar_color);
+			handle_output(&format!("{table}"), cli.no_pager);
+			return
+		}
+		Some(SubCommand::Typos { num, aliases }) => {
+			show_typos(*num, aliases.as_deref(), cli.no_pager);
+			return
+		}
+		Some(SubCommand::Rename { old, new }) => {
+			rename_command(old, new);
+			return
+		}
+		Some(SubCommand::Dirs { action }) => {
+			match action {
+				DirsAction::Mv { old, new } => move_dirs(old, new),
+				DirsAction::Prune { remove, merge } => prune_dirs(*remove, *merge),
+			}
+			return
+		}
+		Some(SubCommand::Compare { period, a, b, num }) => {
+			let config = config_or_exit();
+			let (a, b) = match (a, b) {
+				(Some(a), Some(b)) => (*a, *b),
+				_ => last_two_periods(*period, time::now()),
+			};
+			let output = format_compare(&read_events(), a, b, &config.groups, *num);
+			handle_output(&output, cli.no_pager);
+			return
+		}
+		Some(SubCommand::Dormant { older_than, min_count, num }) => {
+			let config = config_or_exit();
+			let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+			entries.apply_groups(&config.groups);
+			let output = dormant::format_dormant(&entries, &read_events(), *older_than, *min_count, *num, cli.absolute);
+			handle_output(&output, cli.no_pager);
+			return
+		}
+		Some(SubCommand::Summary { json }) => {
+			let config = config_or_exit();
+			let mut entries: Entries = serde_json::from_str(&read_stats()).unwrap_or_default();
+			entries.apply_groups(&config.groups);
+			let summary = summary::Summary::new(&entries);
+			if *json {
+				println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+			} else {
+				handle_output(&summary.display(), cli.no_pager);
+			}
+			return
+		}
+		Some(SubCommand::Next { command, num }) => {
+			show_transitions(command, transitions::Direction::Next, *num, &cli);
+			return
+		}
+		Some(SubCommand::Prev { command, num }) => {
+			show_transitions(command, transitions::Direction::Prev, *num, &cli);
+			return
+		}
+		Some(SubCommand::Heatmap { command }) => {
+			show_heatmap(command.as_deref(), &cli);
+			return
+		}
+		Some(SubCommand::Projects { num }) => {
+			let output = format_projects(&read_events(), *num);
+			handle_output(&output, cli.no_pager);
+			return
+		}
+		None => {}
+	}
+	let config = config_or_exit();
 	let raw = read_stats();
 	let no_pager = cli.no_pager;
 	if cli.json {
-		if !cli.commands.is_empty() {
+		if cli.has_selectors() {
 			let mut json: Value = serde_json::from_str(&raw).unwrap_or_default();
 			let filtered;
 			if let Some(array) = json.as_array_mut() {
 				array.retain(|obj| {
 					obj.get("command")
 						.and_then(Value::as_str)
-						.map(|cmd| cli.commands.contains(&cmd.to_string()))
+						.map(|cmd| cli.selects(cmd))
 						.unwrap_or(false)
 				});
 				filtered = array;
@@ -541,47 +1617,188 @@ fn main() {
 		clear_stats();
 		return
 	}
-	let bar_color = cli.bar_color
-		.as_ref()
-		.map(|s| get_color(s))
-		.transpose()
-		.unwrap_or_else(|e| {
-			eprintln!("{e}");
-			std::process::exit(1);
-		});
-	let mut entries: Entries = serde_json::from_str(&raw).unwrap_or_default();
-	if !cli.commands.is_empty() {
-		entries.0.retain(|ent| cli.commands.contains(&ent.command));
-		if cli.total {
-			let total = entries.0.iter().fold(0, |acc, ent| { acc + ent.count });
-			println!("{total}");
-		} else if cli.long {
-			let mut output = String::new();
-			entries.sort_entries();
-			for entry in entries.0 {
-				writeln!(output, "{}",entry.detail_display()).unwrap();
-			}
-			writeln!(output, "{}", "-".repeat((term_dimensions().0 as f64 * 0.5) as usize)).unwrap();
-			handle_output(&output, no_pager);
+	if let Some(retention) = config.retention {
+		enforce_retention(retention);
+	}
+	let bar_color = bar_color_or_exit(&cli);
+	let entries: Entries = serde_json::from_str(&raw).unwrap_or_default();
+	let mut entries = filter_by_origin(&cli, entries).unwrap_or_else(|e| {
+		eprintln!("{e}");
+		std::process::exit(1);
+	});
+	if paths::saw_legacy_paths() {
+		migrate_legacy_paths();
+	}
+	let grand_total = entries.total();
+	entries.apply_groups(&config.groups);
+	if !cli.kind.is_empty() {
+		entries.retain_entries(|ent| cli.kind.contains(&ent.kind));
+	}
+	if !cli.exclude_kind.is_empty() {
+		entries.retain_entries(|ent| !cli.exclude_kind.contains(&ent.kind));
+	}
+	if !cli.tag.is_empty() && let Err(e) = entries.retain_tags(&config.tags, &cli.tag) {
+		eprintln!("{e}");
+		std::process::exit(1);
+	}
+	if let Some(expr) = &cli.filter {
+		let seen = if expr.uses(Field::Age) || expr.uses(Field::Idle) {
+			read_events().seen_times()
 		} else {
-			let mut cmd_stats = CmdStats { entries, cli };
-			let output = cmd_stats.format_entries(bar_color);
-			handle_output(&output, no_pager);
-		}
-	} else if cli.total {
+			HashMap::new()
+		};
+		let ctx = QueryContext { total: entries.total(), seen, now: time::now() };
+		entries.retain_entries(|ent| expr.eval(ent, &ctx));
+	}
+	if cli.has_selectors() {
+		entries.0.retain(|ent| {
+			cli.selects(&ent.command) ||
+			ent.members.iter().any(|(cmd,_)| cli.selects(cmd))
+		});
+	}
+	if cli.total {
 		let total = entries.0.iter().fold(0, |acc, ent| { acc + ent.count });
 		println!("{total}");
 	} else if cli.long {
 		let mut output = String::new();
+		let events = read_events();
 		entries.sort_entries();
 		for entry in entries.0 {
-			writeln!(output, "{}",entry.detail_display()).unwrap();
+			writeln!(output, "{}",entry.detail_display(&events)).unwrap();
 		}
 		writeln!(output, "{}", "-".repeat((term_dimensions().0 as f64 * 0.5) as usize)).unwrap();
 		handle_output(&output, no_pager);
 	} else {
-		let mut cmd_stats = CmdStats { entries, cli };
-		let output = cmd_stats.format_entries(bar_color);
+		let group_by = cli.group_by;
+		let mut cmd_stats = CmdStats::new(entries, grand_total, cli, &config);
+		let output = if let Some(group_by) = group_by {
+			let events = if group_by == GroupBy::Project { read_events() } else { events::Events::default() };
+			let sections = cmd_stats.entries.partition(group_by, &config.tags, &events).unwrap_or_else(|e| {
+				eprintln!("{e}");
+				std::process::exit(1);
+			});
+			cmd_stats.format_sections(sections, bar_color)
+		} else {
+			cmd_stats.format_entries(bar_color)
+		};
 		handle_output(&output, no_pager);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entries(counts: &[u32]) -> Entries {
+		Entries(counts.iter().enumerate().map(|(i, &count)| Entry {
+			command: format!("cmd{i}"),
+			count,
+			kind: CmdKind::Command,
+			dirs: HashMap::new(),
+			hosts: HashMap::new(),
+			kinds: HashMap::new(),
+			members: vec![],
+			ranking: None,
+		}).collect())
+	}
+
+	fn kept(counts: &[u32], cutoff: Cutoff) -> usize {
+		let mut entries = entries(counts);
+		entries.prune_entries(cutoff);
+		entries.0.len()
+	}
+
+	#[test]
+	fn count_cutoff() {
+		assert_eq!(kept(&[50, 30, 15, 5], Cutoff::Count(2)), 2);
+		assert_eq!(kept(&[50, 30], Cutoff::Count(5)), 2);
+		assert_eq!(kept(&[50, 30], Cutoff::Count(0)), 0);
+	}
+
+	#[test]
+	fn cumulative_cutoff_keeps_the_entry_that_reaches_the_percent() {
+		let counts = [50, 30, 15, 5];
+		let cumulative = |percent| Cutoff::Cumulative { percent, total: 100 };
+		assert_eq!(kept(&counts, cumulative(0.0)), 0);
+		assert_eq!(kept(&counts, cumulative(40.0)), 1);
+		assert_eq!(kept(&counts, cumulative(50.0)), 1);
+		assert_eq!(kept(&counts, cumulative(80.0)), 2);
+		assert_eq!(kept(&counts, cumulative(80.5)), 3);
+		assert_eq!(kept(&counts, cumulative(100.0)), 4);
+		assert_eq!(kept(&counts, cumulative(150.0)), 4);
+	}
+
+	#[test]
+	fn cumulative_cutoff_against_a_larger_total() {
+		// the total can cover entries that were filtered out, in which case the percent may never be reached
+		let cumulative = |percent| Cutoff::Cumulative { percent, total: 200 };
+		assert_eq!(kept(&[50, 30, 15, 5], cumulative(40.0)), 2);
+		assert_eq!(kept(&[50, 30, 15, 5], cumulative(60.0)), 4);
+	}
+
+	#[test]
+	fn cumulative_cutoff_with_no_calls() {
+		assert_eq!(kept(&[], Cutoff::Cumulative { percent: 50.0, total: 0 }), 0);
+		assert_eq!(kept(&[0, 0], Cutoff::Cumulative { percent: 50.0, total: 0 }), 0);
+	}
+
+	fn cmd_stats(entries: Entries, args: &[&str]) -> CmdStats {
+		let cli = Cli::parse_from(["cmdstat"].iter().chain(args));
+		let grand_total = entries.total();
+		CmdStats { entries, scores: HashMap::new(), trends: HashMap::new(), seen: HashMap::new(), now: 0, grand_total, cli }
+	}
+
+	/// The rows of the table shown for `args`, split into words
+	fn table_rows(entries: Entries, args: &[&str]) -> Vec<Vec<String>> {
+		let mut stats = cmd_stats(entries, &[&["--no-header"], args].concat());
+		console::strip_ansi_codes(&stats.format_entries(None))
+			.lines()
+			.map(|line| line.split_whitespace().map(String::from).collect::<Vec<_>>())
+			.filter(|words| words.first().is_some_and(|word| word.parse::<usize>().is_ok()))
+			.collect()
+	}
+
+	#[test]
+	fn rank_and_cumulative_follow_the_count_order_whatever_the_sort() {
+		let mut entries = entries(&[40, 30, 20, 10]);
+		for (entry, kind) in entries.0.iter_mut().zip([CmdKind::Command, CmdKind::Builtin, CmdKind::Alias, CmdKind::Function]) {
+			entry.kind = kind;
+		}
+		let rows = table_rows(entries, &["--columns", "rank,command,type,count,cumulative", "--sort", "type"]);
+		assert_eq!(rows, [
+			["3", "cmd2", "alias", "20", "90.0%"],
+			["2", "cmd1", "builtin", "30", "70.0%"],
+			["1", "cmd0", "command", "40", "40.0%"],
+			["4", "cmd3", "function", "10", "100.0%"],
+		]);
+	}
+
+	#[test]
+	fn rank_and_cumulative_survive_the_cutoff_and_reverse() {
+		let rows = table_rows(entries(&[10, 40, 30, 20]), &["--columns", "rank,command,cumulative", "--sort", "command", "-n", "3", "--reverse"]);
+		assert_eq!(rows, [
+			["3", "cmd3", "90.0%"],
+			["2", "cmd2", "70.0%"],
+			["1", "cmd1", "40.0%"],
+		]);
+	}
+
+	#[test]
+	fn section_rows_are_relative_to_the_section() {
+		let mut entries = entries(&[50, 10, 40]);
+		entries.0[2].kind = CmdKind::Alias;
+		let stats = cmd_stats(entries, &["--columns", "command,count,percent", "-n", "1", "--group-by", "kind"]);
+		let sections = stats.entries.partition(GroupBy::Kind, &Default::default(), &events::Events::default()).unwrap();
+		let output = console::strip_ansi_codes(&stats.format_sections(sections, None)).to_string();
+		let rows: Vec<Vec<&str>> = output.lines()
+			.map(|line| line.split_whitespace().collect::<Vec<_>>())
+			.filter(|words| words.first().is_some_and(|word| word.starts_with("cmd") || *word == "subtotal"))
+			.collect();
+		assert_eq!(rows, [
+			["cmd0", "50", "83%"],
+			["subtotal", "60", "60%"],
+			["cmd2", "40", "100%"],
+			["subtotal", "40", "40%"],
+		]);
+	}
+}
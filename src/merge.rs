@@ -0,0 +1,180 @@
+use std::{cmp::Reverse, collections::HashMap, fs, path::PathBuf};
+
+use crate::{CmdKind, Entries, Entry};
+
+/// A stats file to merge, along with the host its counts are attributed to
+#[derive(Clone,Debug)]
+pub struct MergeSource {
+	pub path: PathBuf,
+	pub host: String,
+}
+
+impl std::str::FromStr for MergeSource {
+	type Err = String;
+	/// Parses `FILE` or `FILE=HOST`. Without an explicit host, the file stem is used.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (path, host) = match s.rsplit_once('=') {
+			Some((path, host)) if !host.is_empty() => (PathBuf::from(path), host.to_string()),
+			_ => {
+				let path = PathBuf::from(s);
+				let host = path.file_stem()
+					.map(|stem| stem.to_string_lossy().to_string())
+					.ok_or_else(|| format!("cmdstat: can't derive a host name from `{s}'"))?;
+				(path, host)
+			}
+		};
+		Ok(Self { path, host })
+	}
+}
+
+impl Entries {
+	/// Combine several stats files into one.
+	///
+	/// Counts, dirs, hosts and kind histories are summed. Entries that don't carry host counts yet are
+	/// attributed to the host of the file they came from. When files disagree on a command's
+	/// kind, the kind backed by the most calls wins, and `unknown` only wins if nothing else was seen.
+	/// Ties go to the kind whose name sorts first.
+	pub fn merge(sources: Vec<(Entries, String)>) -> Entries {
+		let mut merged: Vec<Entry> = vec![];
+		let mut index: HashMap<String,usize> = HashMap::new();
+		let mut kind_votes: HashMap<String, HashMap<CmdKind,u32>> = HashMap::new();
+
+		for (entries, host) in sources {
+			for mut entry in entries.0 {
+				let votes = kind_votes.entry(entry.command.clone())
+					.or_default()
+					.entry(entry.kind)
+					.or_default();
+				*votes = votes.saturating_add(entry.count);
+				if entry.hosts.is_empty() {
+					entry.hosts.insert(host.clone(), entry.count);
+				}
+				match index.get(&entry.command) {
+					Some(&idx) => merged[idx].absorb(entry),
+					None => {
+						index.insert(entry.command.clone(), merged.len());
+						merged.push(entry);
+					}
+				}
+			}
+		}
+
+		for entry in &mut merged {
+			if let Some(votes) = kind_votes.get(&entry.command) {
+				entry.kind = votes.iter()
+					.max_by_key(|&(kind, n)| (*kind != CmdKind::Unknown, *n, Reverse(kind.to_string())))
+					.map(|(kind,_)| *kind)
+					.unwrap_or(entry.kind);
+			}
+		}
+
+		Entries(merged)
+	}
+}
+
+impl Entry {
+	/// Add another entry's counts for the same command into this one. Counts saturate rather than overflow.
+	pub fn absorb(&mut self, other: Entry) {
+		fn add(total: &mut u32, n: u32) {
+			*total = total.saturating_add(n);
+		}
+		add(&mut self.count, other.count);
+		for (dir, n) in other.dirs {
+			add(self.dirs.entry(dir).or_default(), n);
+		}
+		for (host, n) in other.hosts {
+			add(self.hosts.entry(host).or_default(), n);
+		}
+		for (kind, n) in other.kinds {
+			add(self.kinds.entry(kind).or_default(), n);
+		}
+	}
+}
+
+pub fn read_sources(sources: &[MergeSource]) -> Result<Vec<(Entries, String)>,String> {
+	sources.iter()
+		.map(|MergeSource { path, host }| {
+			let raw = fs::read_to_string(path)
+				.map_err(|e| format!("cmdstat: failed to read {}: {e}", path.display()))?;
+			let entries: Entries = serde_json::from_str(&raw)
+				.map_err(|e| format!("cmdstat: failed to parse {}: {e}", path.display()))?;
+			Ok((entries, host.clone()))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entries(json: &str) -> Entries {
+		serde_json::from_str(json).unwrap()
+	}
+
+	fn merge(sources: &[(&str, &str)]) -> Vec<Entry> {
+		let sources = sources.iter().map(|&(json, host)| (entries(json), host.to_string())).collect();
+		let Entries(mut merged) = Entries::merge(sources);
+		merged.sort_by(|a, b| a.command.cmp(&b.command));
+		merged
+	}
+
+	#[test]
+	fn sums_counts_dirs_and_hosts() {
+		let merged = merge(&[
+			(r#"[{"command":"git","count":3,"kind":"command","dirs":{"/a":2,"/b":1}}]"#, "laptop"),
+			(r#"[{"command":"git","count":4,"kind":"command","dirs":{"/a":4}},{"command":"ls","count":1,"kind":"builtin","dirs":{}}]"#, "server"),
+		]);
+		assert_eq!(merged.len(), 2);
+		let git = &merged[0];
+		assert_eq!(git.count, 7);
+		assert_eq!(git.dirs, HashMap::from([(PathBuf::from("/a"), 6), (PathBuf::from("/b"), 1)]));
+		assert_eq!(git.hosts, HashMap::from([("laptop".to_string(), 3), ("server".to_string(), 4)]));
+		assert_eq!(merged[1].hosts, HashMap::from([("server".to_string(), 1)]));
+	}
+
+	#[test]
+	fn keeps_existing_host_counts() {
+		let merged = merge(&[
+			(r#"[{"command":"git","count":5,"kind":"command","dirs":{},"hosts":{"a":2,"b":3}}]"#, "merged"),
+			(r#"[{"command":"git","count":1,"kind":"command","dirs":{}}]"#, "c"),
+		]);
+		assert_eq!(merged[0].hosts, HashMap::from([("a".to_string(), 2), ("b".to_string(), 3), ("c".to_string(), 1)]));
+	}
+
+	#[test]
+	fn kind_goes_to_the_most_calls() {
+		let merged = merge(&[
+			(r#"[{"command":"ll","count":2,"kind":"alias","dirs":{}}]"#, "a"),
+			(r#"[{"command":"ll","count":5,"kind":"function","dirs":{}}]"#, "b"),
+			(r#"[{"command":"ll","count":9,"kind":"unknown","dirs":{}}]"#, "c"),
+		]);
+		assert_eq!(merged[0].kind, CmdKind::Function);
+	}
+
+	#[test]
+	fn kind_ties_go_to_the_first_name() {
+		for hosts in [["a", "b"], ["b", "a"]] {
+			let merged = merge(&[
+				(r#"[{"command":"ll","count":5,"kind":"function","dirs":{}}]"#, hosts[0]),
+				(r#"[{"command":"ll","count":5,"kind":"alias","dirs":{}}]"#, hosts[1]),
+			]);
+			assert_eq!(merged[0].kind, CmdKind::Alias);
+		}
+	}
+
+	#[test]
+	fn unknown_only_wins_alone() {
+		let merged = merge(&[(r#"[{"command":"x","count":5,"kind":"unknown","dirs":{}}]"#, "a")]);
+		assert_eq!(merged[0].kind, CmdKind::Unknown);
+	}
+
+	#[test]
+	fn counts_saturate() {
+		let merged = merge(&[
+			(r#"[{"command":"git","count":4294967295,"kind":"command","dirs":{"/a":4294967295}}]"#, "a"),
+			(r#"[{"command":"git","count":1,"kind":"command","dirs":{"/a":1}}]"#, "b"),
+		]);
+		assert_eq!(merged[0].count, u32::MAX);
+		assert_eq!(merged[0].dirs[&PathBuf::from("/a")], u32::MAX);
+	}
+}
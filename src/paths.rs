@@ -0,0 +1,85 @@
+use std::{collections::HashMap, env, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+static HOME: OnceLock<Option<PathBuf>> = OnceLock::new();
+static SAW_LEGACY: AtomicBool = AtomicBool::new(false);
+
+pub fn home() -> Option<&'static Path> {
+	HOME.get_or_init(|| env::var_os("HOME").map(PathBuf::from))
+		.as_deref()
+}
+
+/// Rewrite a path under $HOME as `~/...`. Other paths are returned unchanged.
+pub fn to_portable(path: &Path) -> PathBuf {
+	match home().and_then(|home| path.strip_prefix(home).ok()) {
+		Some(rest) if rest.as_os_str().is_empty() => PathBuf::from("~"),
+		Some(rest) => Path::new("~").join(rest),
+		None => path.to_path_buf()
+	}
+}
+
+/// Expand a leading `~` back into $HOME. Other paths are returned unchanged.
+pub fn expand(path: &Path) -> PathBuf {
+	match (home(), path.strip_prefix("~")) {
+		(Some(home), Ok(rest)) if rest.as_os_str().is_empty() => home.to_path_buf(),
+		(Some(home), Ok(rest)) => home.join(rest),
+		_ => path.to_path_buf()
+	}
+}
+
+/// Expand a path read from disk, noting whether it was stored in the legacy absolute form
+fn expand_stored(path: PathBuf) -> PathBuf {
+	if path.is_absolute() && home().is_some_and(|home| path.starts_with(home)) {
+		SAW_LEGACY.store(true, Ordering::Relaxed);
+		return path
+	}
+	expand(&path)
+}
+
+/// Whether any path read so far was an absolute path under $HOME, written before paths were stored portably
+pub fn saw_legacy_paths() -> bool {
+	SAW_LEGACY.load(Ordering::Relaxed)
+}
+
+/// Serde adapter storing a path in portable form
+pub mod portable {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(path: &Path, s: S) -> Result<S::Ok, S::Error> {
+		to_portable(path).serialize(s)
+	}
+	pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<PathBuf, D::Error> {
+		PathBuf::deserialize(de).map(expand_stored)
+	}
+}
+
+/// Serde adapter storing an optional path in portable form
+pub mod portable_opt {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(path: &Option<PathBuf>, s: S) -> Result<S::Ok, S::Error> {
+		path.as_deref().map(to_portable).serialize(s)
+	}
+	pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<PathBuf>, D::Error> {
+		Option::<PathBuf>::deserialize(de).map(|path| path.map(expand_stored))
+	}
+}
+
+/// Serde adapter storing the keys of a dir count map in portable form.
+/// Keys that expand to the same path have their counts summed.
+pub mod portable_dirs {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(dirs: &HashMap<PathBuf,u32>, s: S) -> Result<S::Ok, S::Error> {
+		s.collect_map(dirs.iter().map(|(dir, n)| (to_portable(dir), n)))
+	}
+	pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<HashMap<PathBuf,u32>, D::Error> {
+		let raw = HashMap::<PathBuf,u32>::deserialize(de)?;
+		let mut dirs = HashMap::new();
+		for (dir, n) in raw {
+			*dirs.entry(expand_stored(dir)).or_default() += n;
+		}
+		Ok(dirs)
+	}
+}
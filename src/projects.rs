@@ -0,0 +1,55 @@
+use std::{cmp::Reverse, collections::HashMap, fmt::Write, path::PathBuf};
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{events::Events, prettify_dir, table::{Cell, Row, SortValue, Table}};
+
+/// Render the top `num` commands for each git repository in the event log,
+/// busiest repositories first
+pub fn format_projects(events: &Events, num: usize) -> String {
+	let mut projects: HashMap<&PathBuf, HashMap<&str,u32>> = HashMap::new();
+	for event in &events.0 {
+		let Some(repo) = &event.repo else {
+			continue
+		};
+		*projects.entry(repo)
+			.or_default()
+			.entry(&event.command)
+			.or_default() += 1;
+	}
+
+	let mut projects: Vec<_> = projects.into_iter()
+		.map(|(repo, cmds)| {
+			let total: u32 = cmds.values().sum();
+			let mut cmds: Vec<(&str,u32)> = cmds.into_iter().collect();
+			cmds.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+			cmds.truncate(num);
+			(repo, total, cmds)
+		})
+		.collect();
+	projects.sort_by_key(|&(repo, total, _)| (Reverse(total), repo));
+
+	let mut output = String::new();
+	if projects.is_empty() {
+		writeln!(output, "No commands have been recorded inside a git repository yet.").unwrap();
+		return output
+	}
+	for (repo, total, cmds) in projects {
+		let mut table = Table::new()
+			.with_n_columns(3)
+			.with_heading(0, "Command")
+			.with_heading(1, "Count")
+			.with_heading(2, "Percent")
+			.with_title(format!("{} {}", prettify_dir(repo), format!("({total} calls)").with(Color::DarkGrey)));
+		for (cmd, count) in cmds {
+			let percentage = (count as f64 / total as f64) * 100.0;
+			let row = Row::new()
+				.with_cell(Cell::new(cmd))
+				.with_cell(Cell::int(count))
+				.with_cell(Cell::new(format!("{percentage:.01}%")).with_sort_value(SortValue::Float(percentage)));
+			table.add_row(row);
+		}
+		writeln!(output, "{table}").unwrap();
+	}
+	output
+}
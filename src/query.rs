@@ -0,0 +1,492 @@
+use std::{collections::HashMap, fmt::Display};
+
+use regex::Regex;
+
+use crate::{time::parse_duration, Entry};
+
+/// Fields that can be referenced in a `--where` expression
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Field {
+	Command,
+	Count,
+	Kind,
+	Percent,
+	Dirs,
+	Hosts,
+	/// Seconds since the command was first recorded in the event log
+	Age,
+	/// Seconds since the command was last recorded in the event log
+	Idle,
+}
+
+impl Field {
+	fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"command" | "cmd" => Some(Field::Command),
+			"count" | "calls" => Some(Field::Count),
+			"kind" | "type" => Some(Field::Kind),
+			"percent" | "pct" => Some(Field::Percent),
+			"dirs" => Some(Field::Dirs),
+			"hosts" => Some(Field::Hosts),
+			"age" => Some(Field::Age),
+			"idle" => Some(Field::Idle),
+			_ => None
+		}
+	}
+}
+
+#[derive(Clone,Debug)]
+pub enum Value {
+	Num(f64),
+	Str(String),
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum CmpOp {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	Match,
+}
+
+/// A parsed `--where` expression
+#[derive(Clone,Debug)]
+pub enum Expr {
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Not(Box<Expr>),
+	Cmp(Field, CmpOp, Value),
+	Regex(Field, Regex),
+}
+
+/// Everything an expression can be evaluated against, besides the entry itself
+#[derive(Default,Debug)]
+pub struct QueryContext {
+	/// Sum of all counts, for the percent field
+	pub total: u64,
+	/// First and last time each command appears in the event log
+	pub seen: HashMap<String,(u64,u64)>,
+	pub now: u64,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+enum Token {
+	Ident(String),
+	Str(String),
+	Op(&'static str),
+	LParen,
+	RParen,
+}
+
+impl Display for Token {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Token::Ident(s) => write!(f, "{s}"),
+			Token::Str(s) => write!(f, "'{s}'"),
+			Token::Op(op) => write!(f, "{op}"),
+			Token::LParen => write!(f, "("),
+			Token::RParen => write!(f, ")"),
+		}
+	}
+}
+
+const OPERATORS: [&str;11] = ["&&", "||", "==", "!=", "<=", ">=", "=~", "<", ">", "=", "!"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>,String> {
+	let mut tokens = vec![];
+	let mut chars = input.char_indices().peekable();
+	while let Some(&(i, c)) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		} else if c == '(' {
+			chars.next();
+			tokens.push(Token::LParen);
+		} else if c == ')' {
+			chars.next();
+			tokens.push(Token::RParen);
+		} else if c == '\'' || c == '"' {
+			chars.next();
+			let mut s = String::new();
+			loop {
+				match chars.next() {
+					Some((_, ch)) if ch == c => break,
+					Some((_, ch)) => s.push(ch),
+					None => return Err(format!("cmdstat: unterminated string starting at column {}", i + 1))
+				}
+			}
+			tokens.push(Token::Str(s));
+		} else if let Some(op) = OPERATORS.iter().find(|op| input[i..].starts_with(**op)) {
+			for _ in 0..op.len() {
+				chars.next();
+			}
+			tokens.push(Token::Op(op));
+		} else {
+			let mut s = String::new();
+			while let Some(&(_, ch)) = chars.peek() {
+				if ch.is_whitespace() || "()'\"&|=!<>".contains(ch) {
+					break
+				}
+				s.push(ch);
+				chars.next();
+			}
+			if s.is_empty() {
+				return Err(format!("cmdstat: unexpected `{c}' at column {}", i + 1))
+			}
+			tokens.push(Token::Ident(s));
+		}
+	}
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	pos: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.pos)
+	}
+	fn next(&mut self) -> Option<Token> {
+		let tok = self.tokens.get(self.pos).cloned();
+		self.pos += 1;
+		tok
+	}
+	fn eat_op(&mut self, op: &str) -> bool {
+		if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+	fn parse_or(&mut self) -> Result<Expr,String> {
+		let mut lhs = self.parse_and()?;
+		while self.eat_op("||") {
+			let rhs = self.parse_and()?;
+			lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+		}
+		Ok(lhs)
+	}
+	fn parse_and(&mut self) -> Result<Expr,String> {
+		let mut lhs = self.parse_unary()?;
+		while self.eat_op("&&") {
+			let rhs = self.parse_unary()?;
+			lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+		}
+		Ok(lhs)
+	}
+	fn parse_unary(&mut self) -> Result<Expr,String> {
+		if self.eat_op("!") {
+			return Ok(Expr::Not(Box::new(self.parse_unary()?)))
+		}
+		if self.peek() == Some(&Token::LParen) {
+			self.next();
+			let expr = self.parse_or()?;
+			match self.next() {
+				Some(Token::RParen) => return Ok(expr),
+				Some(tok) => return Err(format!("cmdstat: expected `)' but found `{tok}'")),
+				None => return Err("cmdstat: expected `)' but the expression ended".into())
+			}
+		}
+		self.parse_cmp()
+	}
+	fn parse_cmp(&mut self) -> Result<Expr,String> {
+		let field = match self.next() {
+			Some(Token::Ident(name)) => Field::from_name(&name)
+				.ok_or_else(|| format!("cmdstat: unknown field `{name}'"))?,
+			Some(tok) => return Err(format!("cmdstat: expected a field name but found `{tok}'")),
+			None => return Err("cmdstat: expected a field name but the expression ended".into())
+		};
+		let op = match self.next() {
+			Some(Token::Op("==" | "=")) => CmpOp::Eq,
+			Some(Token::Op("!=")) => CmpOp::Ne,
+			Some(Token::Op("<")) => CmpOp::Lt,
+			Some(Token::Op("<=")) => CmpOp::Le,
+			Some(Token::Op(">")) => CmpOp::Gt,
+			Some(Token::Op(">=")) => CmpOp::Ge,
+			Some(Token::Op("=~")) => CmpOp::Match,
+			Some(tok) => return Err(format!("cmdstat: expected a comparison after `{field:?}' but found `{tok}'")),
+			None => return Err("cmdstat: expected a comparison but the expression ended".into())
+		};
+		let raw = match self.next() {
+			Some(Token::Ident(s) | Token::Str(s)) => s,
+			Some(tok) => return Err(format!("cmdstat: expected a value but found `{tok}'")),
+			None => return Err("cmdstat: expected a value but the expression ended".into())
+		};
+		if op == CmpOp::Match {
+			let regex = Regex::new(&raw).map_err(|e| format!("cmdstat: invalid regex `{raw}': {e}"))?;
+			return Ok(Expr::Regex(field, regex))
+		}
+		let value = match field {
+			Field::Command | Field::Kind => Value::Str(raw),
+			Field::Age | Field::Idle => Value::Num(parse_duration(&raw)? as f64),
+			_ => {
+				let num = raw.strip_suffix('%').unwrap_or(&raw);
+				Value::Num(num.parse().map_err(|_| format!("cmdstat: expected a number but found `{raw}'"))?)
+			}
+		};
+		Ok(Expr::Cmp(field, op, value))
+	}
+}
+
+impl Expr {
+	/// Parse an expression like `count > 50 && kind == alias && dirs > 3`
+	pub fn parse(input: &str) -> Result<Expr,String> {
+		let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+		let expr = parser.parse_or()?;
+		if let Some(tok) = parser.peek() {
+			return Err(format!("cmdstat: unexpected `{tok}' in expression"))
+		}
+		Ok(expr)
+	}
+	/// Whether the expression refers to `field` anywhere
+	pub fn uses(&self, field: Field) -> bool {
+		match self {
+			Expr::And(a, b) |
+			Expr::Or(a, b) => a.uses(field) || b.uses(field),
+			Expr::Not(a) => a.uses(field),
+			Expr::Cmp(f, _, _) |
+			Expr::Regex(f, _) => *f == field,
+		}
+	}
+	pub fn eval(&self, entry: &Entry, ctx: &QueryContext) -> bool {
+		match self {
+			Expr::And(a, b) => a.eval(entry, ctx) && b.eval(entry, ctx),
+			Expr::Or(a, b) => a.eval(entry, ctx) || b.eval(entry, ctx),
+			Expr::Not(a) => !a.eval(entry, ctx),
+			Expr::Regex(field, regex) => match field_value(*field, entry, ctx) {
+				Some(Value::Str(s)) => regex.is_match(&s),
+				Some(Value::Num(n)) => regex.is_match(&n.to_string()),
+				None => false
+			},
+			Expr::Cmp(field, op, rhs) => {
+				let Some(lhs) = field_value(*field, entry, ctx) else {
+					return false
+				};
+				let ord = match (&lhs, rhs) {
+					(Value::Num(a), Value::Num(b)) => a.partial_cmp(b),
+					(Value::Str(a), Value::Str(b)) => Some(a.as_str().cmp(b.as_str())),
+					_ => None
+				};
+				let Some(ord) = ord else {
+					return false
+				};
+				match op {
+					CmpOp::Eq => ord.is_eq(),
+					CmpOp::Ne => ord.is_ne(),
+					CmpOp::Lt => ord.is_lt(),
+					CmpOp::Le => ord.is_le(),
+					CmpOp::Gt => ord.is_gt(),
+					CmpOp::Ge => ord.is_ge(),
+					CmpOp::Match => unreachable!(),
+				}
+			}
+		}
+	}
+}
+
+/// The value of `field` for `entry`. Time fields are `None` for commands missing from the event log.
+fn field_value(field: Field, entry: &Entry, ctx: &QueryContext) -> Option<Value> {
+	let seen = || entry.seen(&ctx.seen);
+	let value = match field {
+		Field::Command => Value::Str(entry.command.clone()),
+		Field::Kind => Value::Str(entry.kind.to_string()),
+		Field::Count => Value::Num(entry.count as f64),
+		Field::Percent => Value::Num(if ctx.total == 0 { 0.0 } else { entry.count as f64 / ctx.total as f64 * 100.0 }),
+		Field::Dirs => Value::Num(entry.dirs.len() as f64),
+		Field::Hosts => Value::Num(entry.hosts.len() as f64),
+		Field::Age => Value::Num(ctx.now.saturating_sub(seen()?.0) as f64),
+		Field::Idle => Value::Num(ctx.now.saturating_sub(seen()?.1) as f64),
+	};
+	Some(value)
+}
+
+/// Compile a shell style glob into an anchored regex. Supports `*`, `?` and `[...]` classes,
+/// which are negated by a leading `!` or `^`.
+pub fn glob_to_regex(glob: &str) -> Result<Regex,String> {
+	let mut pattern = String::from("^");
+	let mut chars = glob.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'*' => pattern.push_str(".*"),
+			'?' => pattern.push('.'),
+			'[' => {
+				pattern.push('[');
+				if let Some(rest) = chars.as_str().strip_prefix(['!', '^']) {
+					pattern.push('^');
+					chars = rest.chars();
+				}
+				for c in chars.by_ref() {
+					if c == ']' {
+						break
+					}
+					if c == '\\' {
+						pattern.push('\\');
+					}
+					pattern.push(c);
+				}
+				pattern.push(']');
+			}
+			_ => pattern.push_str(&regex::escape(&c.to_string())),
+		}
+	}
+	pattern.push('$');
+	Regex::new(&pattern).map_err(|e| format!("cmdstat: invalid glob `{glob}': {e}"))
+}
+
+pub fn parse_regex(pattern: &str) -> Result<Regex,String> {
+	Regex::new(pattern).map_err(|e| format!("cmdstat: invalid regex `{pattern}': {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{time::DAY, CmdKind};
+
+	fn entry(command: &str, count: u32, kind: CmdKind) -> Entry {
+		Entry {
+			command: command.to_string(),
+			count,
+			kind,
+			dirs: HashMap::new(),
+			hosts: HashMap::new(),
+			kinds: HashMap::new(),
+			members: vec![],
+			ranking: None,
+		}
+	}
+
+	fn matches(input: &str, entry: &Entry) -> bool {
+		Expr::parse(input).unwrap().eval(entry, &QueryContext::default())
+	}
+
+	fn parse_err(input: &str) -> String {
+		Expr::parse(input).unwrap_err()
+	}
+
+	#[test]
+	fn and_binds_tighter_than_or() {
+		let query = "count > 5 || count < 2 && kind == alias";
+		assert!(matches(query, &entry("git", 10, CmdKind::Command)));
+		assert!(matches(query, &entry("g", 1, CmdKind::Alias)));
+		assert!(!matches(query, &entry("gst", 1, CmdKind::Command)));
+		assert!(!matches(query, &entry("ls", 3, CmdKind::Alias)));
+	}
+
+	#[test]
+	fn parentheses_override_precedence() {
+		let query = "(count > 5 || count < 2) && kind == alias";
+		assert!(!matches(query, &entry("git", 10, CmdKind::Command)));
+		assert!(matches(query, &entry("g", 10, CmdKind::Alias)));
+	}
+
+	#[test]
+	fn not_applies_to_the_next_operand() {
+		assert!(matches("!count > 5", &entry("ls", 3, CmdKind::Command)));
+		assert!(matches("!count > 5 && kind == alias", &entry("g", 3, CmdKind::Alias)));
+		assert!(!matches("!(count > 5 || kind == alias)", &entry("g", 3, CmdKind::Alias)));
+		assert!(matches("!!kind == alias", &entry("g", 3, CmdKind::Alias)));
+	}
+
+	#[test]
+	fn quoted_values() {
+		let ent = entry("git status", 1, CmdKind::Command);
+		assert!(matches("command == 'git status'", &ent));
+		assert!(matches("cmd = \"git status\"", &ent));
+		assert!(matches("command != 'git && ls'", &ent));
+	}
+
+	#[test]
+	fn percent_and_numbers() {
+		let ctx = QueryContext { total: 200, ..Default::default() };
+		let ent = entry("ls", 50, CmdKind::Command);
+		assert!(Expr::parse("percent >= 25%").unwrap().eval(&ent, &ctx));
+		assert!(!Expr::parse("pct > 25").unwrap().eval(&ent, &ctx));
+		assert!(matches("count == 50", &ent));
+		assert!(matches("count <= 50.5", &ent));
+	}
+
+	#[test]
+	fn regex_matches() {
+		let ent = entry("git", 12, CmdKind::Command);
+		assert!(matches("command =~ '^g'", &ent));
+		assert!(!matches("command =~ '^it'", &ent));
+		assert!(matches("kind =~ com", &ent));
+		assert!(matches("count =~ '^1[0-9]$'", &ent));
+	}
+
+	#[test]
+	fn duration_comparisons() {
+		let now = 100 * DAY;
+		let ctx = QueryContext {
+			seen: HashMap::from([("git".to_string(), (now - 30 * DAY, now - 3 * DAY))]),
+			now,
+			..Default::default()
+		};
+		let ent = entry("git", 1, CmdKind::Command);
+		let eval = |input: &str, ent: &Entry| Expr::parse(input).unwrap().eval(ent, &ctx);
+		assert!(eval("idle > 2d", &ent));
+		assert!(!eval("idle > 1w", &ent));
+		assert!(eval("age >= 30d && age < 5w", &ent));
+		assert!(eval("idle == 259200", &ent));
+		// commands missing from the event log have no age or idle time
+		let unseen = entry("ls", 1, CmdKind::Command);
+		assert!(!eval("idle > 0", &unseen));
+		assert!(!eval("idle <= 0", &unseen));
+		assert!(eval("!(idle > 0)", &unseen));
+	}
+
+	#[test]
+	fn uses_finds_nested_fields() {
+		let expr = Expr::parse("count > 1 || !(kind == alias && idle > 1d)").unwrap();
+		assert!(expr.uses(Field::Idle));
+		assert!(expr.uses(Field::Kind));
+		assert!(!expr.uses(Field::Age));
+	}
+
+	#[test]
+	fn error_messages() {
+		assert_eq!(parse_err("size > 3"), "cmdstat: unknown field `size'");
+		assert_eq!(parse_err("(count > 3"), "cmdstat: expected `)' but the expression ended");
+		assert_eq!(parse_err("(count > 3 kind"), "cmdstat: expected `)' but found `kind'");
+		assert_eq!(parse_err("count > 3)"), "cmdstat: unexpected `)' in expression");
+		assert_eq!(parse_err("command == 'git"), "cmdstat: unterminated string starting at column 12");
+		assert_eq!(parse_err("count > lots"), "cmdstat: expected a number but found `lots'");
+		assert_eq!(parse_err("count"), "cmdstat: expected a comparison but the expression ended");
+		assert_eq!(parse_err("count kind"), "cmdstat: expected a comparison after `Count' but found `kind'");
+		assert_eq!(parse_err("count >"), "cmdstat: expected a value but the expression ended");
+		assert_eq!(parse_err("count > &&"), "cmdstat: expected a value but found `&&'");
+		assert_eq!(parse_err("> 3"), "cmdstat: expected a field name but found `>'");
+		assert_eq!(parse_err(""), "cmdstat: expected a field name but the expression ended");
+		assert_eq!(parse_err("idle > 3x"), "cmdstat: invalid duration unit `x' in `3x'");
+		assert!(parse_err("command =~ '('").starts_with("cmdstat: invalid regex `(': "));
+	}
+
+	#[test]
+	fn globs() {
+		let glob = |g: &str| glob_to_regex(g).unwrap();
+		assert!(glob("g*").is_match("git"));
+		assert!(glob("g*").is_match("g"));
+		assert!(!glob("g*").is_match("agit"));
+		assert!(glob("?s").is_match("ls"));
+		assert!(!glob("?s").is_match("lls"));
+		assert!(glob("[gh]it").is_match("hit"));
+		assert!(!glob("[gh]it").is_match("kit"));
+		assert!(glob("[a-c]d").is_match("bd"));
+		assert!(glob("[!gh]it").is_match("kit"));
+		assert!(!glob("[!gh]it").is_match("git"));
+		assert!(!glob("[!gh]it").is_match("hit"));
+		assert!(glob("[^gh]it").is_match("kit"));
+		assert!(glob("[a!]x").is_match("!x"));
+		assert!(glob("[a,b]*").is_match("b,c"));
+		assert!(glob("a.b").is_match("a.b"));
+		assert!(!glob("a.b").is_match("axb"));
+		assert!(glob("c++").is_match("c++"));
+		assert!(glob("[^\\]x").is_match("ax"));
+		assert!(glob_to_regex("[z-a]").unwrap_err().starts_with("cmdstat: invalid glob `[z-a]': "));
+	}
+}
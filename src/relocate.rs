@@ -0,0 +1,133 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use crate::{events::{Event, Events}, Entries, Entry};
+
+/// Move `path` from under `old` to under `new`, if it lives there
+fn rebase(path: &Path, old: &Path, new: &Path) -> Option<PathBuf> {
+	let rest = path.strip_prefix(old).ok()?;
+	if rest.as_os_str().is_empty() {
+		Some(new.to_path_buf())
+	} else {
+		Some(new.join(rest))
+	}
+}
+
+/// The closest ancestor of `path` that still exists on disk
+fn nearest_existing(path: &Path) -> Option<PathBuf> {
+	path.ancestors()
+		.skip(1)
+		.find(|dir| dir.is_dir())
+		.map(Path::to_path_buf)
+}
+
+/// Name of this machine, as the plugin records it
+pub fn local_host() -> String {
+	let mut buf = [0u8; 256];
+	if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+		return String::new()
+	}
+	let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+	String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+impl Entry {
+	/// Whether every call was made on `host`, so that its dirs can be checked against the local disk.
+	/// Entries without host counts predate them and are taken to be local.
+	fn is_local(&self, host: &str) -> bool {
+		self.hosts.keys().all(|h| h == host)
+	}
+}
+
+impl Event {
+	fn is_local(&self, host: &str) -> bool {
+		self.host.is_empty() || self.host == host
+	}
+}
+
+impl Entries {
+	/// Rewrite every dir under `old` to live under `new` instead, summing counts that collide.
+	/// Returns the number of dir counts that were moved.
+	pub fn rename_dirs(&mut self, old: &Path, new: &Path) -> u32 {
+		let mut moved = 0;
+		for entry in &mut self.0 {
+			let dirs = std::mem::take(&mut entry.dirs);
+			for (dir, n) in dirs {
+				let dir = match rebase(&dir, old, new) {
+					Some(rebased) => {
+						moved += n;
+						rebased
+					}
+					None => dir
+				};
+				*entry.dirs.entry(dir).or_default() += n;
+			}
+		}
+		moved
+	}
+	/// Every dir recorded on `host` that no longer exists on disk, with its total count across all commands
+	pub fn vanished_dirs(&self, host: &str) -> Vec<(PathBuf,u32)> {
+		let mut vanished: HashMap<&PathBuf,u32> = HashMap::new();
+		for entry in self.0.iter().filter(|ent| ent.is_local(host)) {
+			for (dir, n) in &entry.dirs {
+				if !dir.is_dir() {
+					*vanished.entry(dir).or_default() += n;
+				}
+			}
+		}
+		let mut vanished: Vec<(PathBuf,u32)> = vanished.into_iter()
+			.map(|(dir, n)| (dir.clone(), n))
+			.collect();
+		vanished.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		vanished
+	}
+	/// The number of commands also run on hosts other than `host` that have dirs missing here.
+	/// Those dirs can't be told apart from ones that only exist on the other hosts, so they're left alone.
+	pub fn unchecked_commands(&self, host: &str) -> usize {
+		self.0.iter()
+			.filter(|ent| !ent.is_local(host) && ent.dirs.keys().any(|dir| !dir.is_dir()))
+			.count()
+	}
+	/// Drop dirs recorded on `host` that no longer exist. With `merge`, their counts are folded into
+	/// the nearest ancestor that still exists instead of being discarded.
+	pub fn prune_dirs(&mut self, merge: bool, host: &str) {
+		for entry in self.0.iter_mut().filter(|ent| ent.is_local(host)) {
+			let dirs = std::mem::take(&mut entry.dirs);
+			for (dir, n) in dirs {
+				let dir = if dir.is_dir() {
+					dir
+				} else if let Some(parent) = nearest_existing(&dir).filter(|_| merge) {
+					parent
+				} else {
+					continue
+				};
+				*entry.dirs.entry(dir).or_default() += n;
+			}
+		}
+	}
+}
+
+impl Events {
+	/// Rewrite the dir and repo of every event under `old` to live under `new` instead
+	pub fn rename_dirs(&mut self, old: &Path, new: &Path) {
+		for event in &mut self.0 {
+			if let Some(dir) = rebase(&event.dir, old, new) {
+				event.dir = dir;
+			}
+			if let Some(repo) = event.repo.as_ref().and_then(|repo| rebase(repo, old, new)) {
+				event.repo = Some(repo);
+			}
+		}
+	}
+	/// Point events recorded on `host` in dirs that no longer exist at the nearest ancestor that still does
+	pub fn merge_vanished_dirs(&mut self, host: &str) {
+		for event in self.0.iter_mut().filter(|ev| ev.is_local(host)) {
+			if !event.dir.is_dir() && let Some(parent) = nearest_existing(&event.dir) {
+				event.dir = parent;
+			}
+		}
+	}
+	/// Take out the events recorded on `host` in dirs that no longer exist
+	pub fn extract_vanished_dirs(&mut self, host: &str) -> Vec<Event> {
+		self.extract(|ev| ev.is_local(host) && !ev.dir.is_dir())
+	}
+}
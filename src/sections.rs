@@ -0,0 +1,132 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap}, path::PathBuf, str::FromStr};
+
+use crate::{events::Events, paths, Entries, Entry};
+
+/// What to split the table into sections by
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum GroupBy {
+	Kind,
+	Tag,
+	Host,
+	Project,
+}
+
+impl FromStr for GroupBy {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"kind" | "type" => Ok(GroupBy::Kind),
+			"tag" => Ok(GroupBy::Tag),
+			"host" => Ok(GroupBy::Host),
+			"project" | "repo" => Ok(GroupBy::Project),
+			_ => Err(format!("cmdstat: can't group by `{}', expected 'kind', 'tag', 'host' or 'project'", s))
+		}
+	}
+}
+
+/// Section names for entries that don't fit under any tag, host or project
+const FALLBACKS: [&str;3] = ["(untagged)", "(unknown host)", "(no project)"];
+
+/// A named subset of the entries, shown as its own table
+#[derive(Debug)]
+pub struct Section {
+	pub name: String,
+	pub entries: Entries,
+}
+
+impl Section {
+	pub fn total(&self) -> u64 {
+		self.entries.total()
+	}
+}
+
+impl Entry {
+	/// Split this entry's calls into parts, one per label. Calls not covered by `parts` go under `rest`.
+	fn split(&self, parts: impl IntoIterator<Item = (String,u32)>, rest: &str) -> Vec<(String,Entry)> {
+		let mut remaining = self.count;
+		let mut split = vec![];
+		for (label, n) in parts {
+			let n = n.min(remaining);
+			if n == 0 {
+				continue
+			}
+			remaining -= n;
+			split.push((label, Entry { count: n, ..self.clone() }));
+		}
+		if remaining > 0 {
+			split.push((rest.to_string(), Entry { count: remaining, ..self.clone() }));
+		}
+		split
+	}
+}
+
+impl Entries {
+	/// Partition the entries into sections, busiest first, with the leftovers last.
+	///
+	/// Kind and tag sections hold whole entries, and an entry with several tags shows up under each of them.
+	/// Host and project sections split an entry's calls between the hosts or repositories they were made in.
+	pub fn partition(&self, by: GroupBy, tags: &BTreeMap<String,Vec<String>>, events: &Events) -> Result<Vec<Section>,String> {
+		let mut sections: BTreeMap<String,Vec<Entry>> = BTreeMap::new();
+		match by {
+			GroupBy::Kind => {
+				for entry in &self.0 {
+					sections.entry(entry.kind.to_string()).or_default().push(entry.clone());
+				}
+			}
+			GroupBy::Tag => {
+				if tags.is_empty() {
+					return Err(format!("cmdstat: no tags are defined in {}", crate::config::config_file().display()))
+				}
+				for entry in &self.0 {
+					let mut tagged = false;
+					for tag in tags.keys().filter(|tag| entry.has_tag(tags, tag)) {
+						sections.entry(tag.clone()).or_default().push(entry.clone());
+						tagged = true;
+					}
+					if !tagged {
+						sections.entry(FALLBACKS[0].into()).or_default().push(entry.clone());
+					}
+				}
+			}
+			GroupBy::Host => {
+				for entry in &self.0 {
+					let mut hosts: Vec<(String,u32)> = entry.hosts.iter().map(|(h,n)| (h.clone(),*n)).collect();
+					hosts.sort_by_key(|(h,n)| (Reverse(*n), h.clone()));
+					for (host, mut part) in entry.split(hosts, FALLBACKS[1]) {
+						part.hosts.retain(|h,_| *h == host);
+						sections.entry(host).or_default().push(part);
+					}
+				}
+			}
+			GroupBy::Project => {
+				let mut repos: HashMap<&str, HashMap<&PathBuf,u32>> = HashMap::new();
+				for event in &events.0 {
+					if let Some(repo) = &event.repo {
+						*repos.entry(&event.command).or_default().entry(repo).or_default() += 1;
+					}
+				}
+				for entry in &self.0 {
+					let mut counts: HashMap<&PathBuf,u32> = HashMap::new();
+					for cmd in entry.member_names() {
+						for (repo, n) in repos.get(cmd).into_iter().flatten() {
+							*counts.entry(repo).or_default() += n;
+						}
+					}
+					let mut counts: Vec<(String,u32)> = counts.into_iter()
+						.map(|(repo, n)| (paths::to_portable(repo).to_string_lossy().to_string(), n))
+						.collect();
+					counts.sort_by_key(|(repo,n)| (Reverse(*n), repo.clone()));
+					for (repo, part) in entry.split(counts, FALLBACKS[2]) {
+						sections.entry(repo).or_default().push(part);
+					}
+				}
+			}
+		}
+
+		let mut sections: Vec<Section> = sections.into_iter()
+			.map(|(name, entries)| Section { name, entries: Entries(entries) })
+			.collect();
+		sections.sort_by_key(|section| (FALLBACKS.contains(&section.name.as_str()), Reverse(section.total())));
+		Ok(sections)
+	}
+}
@@ -0,0 +1,94 @@
+use std::{collections::{HashMap, HashSet}, fmt::Write};
+
+use crossterm::style::{Color, Stylize};
+use serde::Serialize;
+
+use crate::{CmdKind, Entries};
+
+/// How many of the busiest commands the top share is taken over
+const TOP_N: usize = 10;
+
+/// Calls and commands of a single kind
+#[derive(Serialize,Debug)]
+pub struct KindSummary {
+	pub kind: CmdKind,
+	pub commands: usize,
+	pub calls: u64,
+}
+
+/// Figures describing the whole stats file
+#[derive(Serialize,Debug)]
+pub struct Summary {
+	pub calls: u64,
+	pub commands: usize,
+	pub kinds: Vec<KindSummary>,
+	/// Percentage of calls made to the ten busiest commands
+	pub top_share: f64,
+	/// Number of commands called exactly once
+	pub long_tail: usize,
+	/// Shannon entropy of the spread of calls across commands, in bits
+	pub entropy: f64,
+	/// The entropy if every command were called equally often
+	pub max_entropy: f64,
+	pub dirs: usize,
+}
+
+impl Summary {
+	pub fn new(entries: &Entries) -> Self {
+		let calls = entries.total();
+		let commands = entries.0.len();
+
+		let mut kinds: HashMap<CmdKind,KindSummary> = HashMap::new();
+		for entry in &entries.0 {
+			let summary = kinds.entry(entry.kind).or_insert(KindSummary { kind: entry.kind, commands: 0, calls: 0 });
+			summary.commands += 1;
+			summary.calls += entry.count as u64;
+		}
+		let mut kinds: Vec<KindSummary> = kinds.into_values().collect();
+		kinds.sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.kind.to_string().cmp(&b.kind.to_string())));
+
+		let mut counts: Vec<u32> = entries.0.iter().map(|ent| ent.count).collect();
+		counts.sort_unstable_by(|a, b| b.cmp(a));
+		let top: u64 = counts.iter().take(TOP_N).map(|&n| n as u64).sum();
+		let share = |n: u64| if calls == 0 { 0.0 } else { n as f64 / calls as f64 };
+
+		let entropy = counts.iter()
+			.map(|&n| share(n as u64))
+			.filter(|&p| p > 0.0)
+			.fold(0.0, |entropy, p| entropy - p * p.log2());
+		let max_entropy = if commands > 1 { (commands as f64).log2() } else { 0.0 };
+
+		let dirs: HashSet<_> = entries.0.iter().flat_map(|ent| ent.dirs.keys()).collect();
+
+		Self {
+			calls,
+			commands,
+			kinds,
+			top_share: share(top) * 100.0,
+			long_tail: counts.iter().filter(|&&n| n == 1).count(),
+			entropy,
+			max_entropy,
+			dirs: dirs.len(),
+		}
+	}
+	pub fn display(&self) -> String {
+		let mut display = String::new();
+		let label = |s: &str| s.to_string().with(Color::Cyan).bold();
+		writeln!(display, "{}", "Summary".with(Color::Cyan).bold()).unwrap();
+		writeln!(display, "{}", "-".repeat(30)).unwrap();
+		writeln!(display, "{}: {}", label("calls"), self.calls).unwrap();
+		writeln!(display, "{}: {}", label("commands"), self.commands).unwrap();
+		writeln!(display, "{}: {:.01}%", label(&format!("top {TOP_N} share")), self.top_share).unwrap();
+		writeln!(display, "{}: {} commands used once", label("long tail"), self.long_tail).unwrap();
+		let evenness = if self.max_entropy > 0.0 { self.entropy / self.max_entropy * 100.0 } else { 0.0 };
+		writeln!(display, "{}: {:.02} bits ({evenness:.0}% of the {:.02} possible)", label("entropy"), self.entropy, self.max_entropy).unwrap();
+		writeln!(display, "{}: {}", label("directories"), self.dirs).unwrap();
+		if !self.kinds.is_empty() {
+			writeln!(display, "{}: ", label("kinds")).unwrap();
+			for KindSummary { kind, commands, calls } in &self.kinds {
+				writeln!(display, "\t{}: {calls} calls across {commands} commands", kind.to_string().with(Color::Blue)).unwrap();
+			}
+		}
+		display
+	}
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{cmp::Ordering, fmt::Display};
 
 use crossterm::style::{Color, Stylize};
 use unicode_width::UnicodeWidthStr;
@@ -10,9 +10,11 @@ pub struct Table {
 	columns: usize,
 	rows: Vec<Row>,
 	spacer: Option<char>,
-	sort_by: Option<usize>,
+	sort_by: Vec<(usize, SortOrder)>,
 	reverse: bool,
-	no_header: bool
+	no_header: bool,
+	/// A row shown below the others, which doesn't take part in sorting
+	footer: Option<Row>
 }
 
 impl Table {
@@ -20,18 +22,18 @@ impl Table {
 		Self::default()
 	}
 	pub fn with_n_columns(self, n: usize) -> Self {
-		let Self { title, headings, columns: _, rows, spacer, sort_by, reverse, no_header } = self;
-		Self { title, headings, columns: n, rows, spacer, sort_by, reverse, no_header }
+		let Self { title, headings, columns: _, rows, spacer, sort_by, reverse, no_header, footer } = self;
+		Self { title, headings, columns: n, rows, spacer, sort_by, reverse, no_header, footer }
 	}
 	pub fn with_heading<S: ToString>(self, field_num: usize, heading: S) -> Self {
 		assert!(field_num < self.columns);
-		let Self { title, mut headings, columns, rows, spacer, sort_by, reverse, no_header } = self;
+		let Self { title, mut headings, columns, rows, spacer, sort_by, reverse, no_header, footer } = self;
 		headings.insert(field_num, heading.to_string());
-		Self { title, headings, columns, rows, spacer, sort_by, reverse, no_header }
+		Self { title, headings, columns, rows, spacer, sort_by, reverse, no_header, footer }
 	}
 	pub fn with_title<S: ToString>(self, title: S) -> Self {
-		let Self { title: _, headings, columns, rows, spacer, sort_by, reverse, no_header } = self;
-		Self { title: Some(title.to_string()), headings, columns, rows, spacer, sort_by, reverse, no_header }
+		let Self { title: _, headings, columns, rows, spacer, sort_by, reverse, no_header, footer } = self;
+		Self { title: Some(title.to_string()), headings, columns, rows, spacer, sort_by, reverse, no_header, footer }
 	}
 	pub fn omit_header(&mut self, yn: bool) {
 		self.no_header = yn;
@@ -39,6 +41,9 @@ impl Table {
 	pub fn add_row(&mut self, row: Row) {
 		self.rows.push(row)
 	}
+	pub fn set_footer(&mut self, row: Row) {
+		self.footer = Some(row)
+	}
 	pub fn find_col_idx<S: ToString>(&self, column: S) -> Option<usize> {
 		let col_name = column.to_string();
 		self.headings.iter().position(|h| h == &col_name)
@@ -49,7 +54,7 @@ impl Table {
 	pub fn calc_cell_widths(&self) -> Vec<usize> {
 		let mut widths = vec![];
 		Self::update_cell_widths(&mut widths, self.headings.iter().map(|s| s.as_str()));
-		for row in &self.rows {
+		for row in self.rows.iter().chain(&self.footer) {
 			let Row { cells } = &row;
 			assert!(cells.len() == self.columns);
 			Self::update_cell_widths(&mut widths, cells.iter().map(|c| c.content.as_str()));
@@ -69,41 +74,48 @@ impl Table {
 		}
 	}
 	pub fn set_sort_column(&mut self, col_idx: usize) {
-		self.sort_by = Some(col_idx)
+		self.sort_by = vec![(col_idx, SortOrder::Natural)]
+	}
+	/// Add a column to break ties left by the previous sort keys
+	pub fn add_sort_key(&mut self, col_idx: usize, order: SortOrder) {
+		self.sort_by.push((col_idx, order))
 	}
 	pub fn sort(&mut self) {
-		let col_idx = self.sort_by.unwrap_or_default();
-		assert!((0..self.columns).contains(&col_idx));
-
-		self.rows.sort_by(|a, b| {
-			let cell_a = &a.cells[col_idx];
-			let cell_b = &b.cells[col_idx];
-			match (cell_a.as_number(), cell_b.as_number()) {
-				(Some(an), Some(bn)) => {
-					let ord = bn.cmp(&an);
-					if self.reverse {
-						ord.reverse()
-					} else {
-						ord
-					}
-				}
-				_ => {
-					let ord = if &self.headings[col_idx] == "Usage" { // FIXME: I don't like hard coding this
-						cell_a.content.width().cmp(&cell_b.content.width())
-					} else {
-						cell_b.content.cmp(&cell_a.content)
-					};
-					if self.reverse {
-						ord
-					} else {
-						ord.reverse()
-					}
+		let keys = if self.sort_by.is_empty() { vec![(0, SortOrder::Natural)] } else { self.sort_by.clone() };
+		assert!(keys.iter().all(|(col_idx,_)| (0..self.columns).contains(col_idx)));
+
+		let mut rows = std::mem::take(&mut self.rows);
+		rows.sort_by(|a, b| {
+			for &(col_idx, order) in &keys {
+				let value_a = a.cells[col_idx].sort_value();
+				let value_b = b.cells[col_idx].sort_value();
+				let ord = value_a.cmp(value_b);
+				let descending = match order {
+					SortOrder::Natural => value_a.natural_desc(),
+					SortOrder::Ascending => false,
+					SortOrder::Descending => true,
+				};
+				let ord = if descending { ord.reverse() } else { ord };
+				let ord = if self.reverse { ord.reverse() } else { ord };
+				if ord != Ordering::Equal {
+					return ord
 				}
 			}
+			Ordering::Equal
 		});
+		self.rows = rows;
 	}
 }
 
+/// Direction for a sort key. Natural order is descending for numbers, durations and timestamps, and ascending for ranks and text.
+#[derive(Clone,Copy,PartialEq,Default,Debug)]
+pub enum SortOrder {
+	#[default]
+	Natural,
+	Ascending,
+	Descending,
+}
+
 impl Display for Table {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		let mut widths = self.calc_cell_widths();
@@ -134,15 +146,11 @@ impl Display for Table {
 
 		// rows
 		for row in &self.rows {
-			for (i, cell) in row.cells.iter().enumerate() {
-				let padded = format!("{:<width$} ", cell.content, width = widths[i]);
-				if let Some(color) = cell.color {
-					write!(f, "{}", padded.with(color))?;
-				} else {
-					write!(f, "{padded}")?;
-				}
-			}
-			writeln!(f)?;
+			row.write(f, &widths)?;
+		}
+		if let Some(footer) = &self.footer {
+			writeln!(f, "{}", "-".repeat(widths.iter().sum::<usize>() + self.columns))?;
+			footer.write(f, &widths)?;
 		}
 		if !self.no_header {
 			writeln!(f, "{}", "-".repeat(widths.iter().sum::<usize>() + self.columns))?;
@@ -166,11 +174,71 @@ impl Row {
 		cells.push(cell);
 		Self { cells }
 	}
+	fn write(&self, f: &mut std::fmt::Formatter<'_>, widths: &[usize]) -> std::fmt::Result {
+		for (i, cell) in self.cells.iter().enumerate() {
+			let padded = format!("{:<width$} ", cell.content, width = widths[i]);
+			if let Some(color) = cell.color {
+				write!(f, "{}", padded.with(color))?;
+			} else {
+				write!(f, "{padded}")?;
+			}
+		}
+		writeln!(f)
+	}
 }
 
-#[derive(Default,Debug)]
+/// The value a cell sorts by, kept separate from what it displays
+#[derive(Clone,PartialEq,Debug)]
+pub enum SortValue {
+	Int(i64),
+	Float(f64),
+	/// Length of time in seconds
+	Duration(u64),
+	/// Unix time in seconds
+	Timestamp(u64),
+	/// Position in a ranking, where 1 comes first
+	Rank(u64),
+	Text(String),
+}
+
+impl SortValue {
+	/// Compare in ascending order. Values of different types order numbers first, then text.
+	fn cmp(&self, other: &Self) -> Ordering {
+		use SortValue::*;
+		match (self, other) {
+			(Int(a), Int(b)) => a.cmp(b),
+			(Duration(a), Duration(b)) |
+			(Timestamp(a), Timestamp(b)) |
+			(Rank(a), Rank(b)) => a.cmp(b),
+			(Text(a), Text(b)) => a.cmp(b),
+			(a, b) => match (a.as_f64(), b.as_f64()) {
+				(Some(a), Some(b)) => a.total_cmp(&b),
+				(Some(_), None) => Ordering::Less,
+				(None, Some(_)) => Ordering::Greater,
+				(None, None) => Ordering::Equal,
+			}
+		}
+	}
+	fn as_f64(&self) -> Option<f64> {
+		match self {
+			SortValue::Int(n) => Some(*n as f64),
+			SortValue::Float(n) => Some(*n),
+			SortValue::Duration(n) |
+			SortValue::Timestamp(n) |
+			SortValue::Rank(n) => Some(*n as f64),
+			SortValue::Text(_) => None,
+		}
+	}
+	/// Whether this kind of value sorts in descending order by default
+	fn natural_desc(&self) -> bool {
+		!matches!(self, SortValue::Text(_) | SortValue::Rank(_))
+	}
+}
+
+#[derive(Debug)]
 pub struct Cell {
 	content: String,
+	sort_value: SortValue,
 	append_spacer: bool,
 	truncate_for_space: bool,
 	color: Option<Color>
@@ -178,26 +246,131 @@ pub struct Cell {
 
 impl Cell {
 	pub fn new<S: ToString>(content: S) -> Self {
+		let content = content.to_string();
 		Self {
-			content: content.to_string(),
+			sort_value: SortValue::Text(content.clone()),
+			content,
 			append_spacer: true,
 			truncate_for_space: false,
 			color: None
 		}
 	}
+	/// A cell displaying an integer, sorted numerically
+	pub fn int<N: Display + Copy>(n: N) -> Self where i64: TryFrom<N> {
+		Self::new(n).with_sort_value(SortValue::Int(i64::try_from(n).unwrap_or(i64::MAX)))
+	}
+	pub fn with_sort_value(self, sort_value: SortValue) -> Self {
+		let Self { content, sort_value: _, append_spacer, truncate_for_space, color } = self;
+		Self { content, sort_value, append_spacer, truncate_for_space, color }
+	}
 	pub fn append_spacer(self, yn: bool) -> Self {
-		let Self { content, append_spacer: _, truncate_for_space, color } = self;
-		Self { content, append_spacer: yn, truncate_for_space, color }
+		let Self { content, sort_value, append_spacer: _, truncate_for_space, color } = self;
+		Self { content, sort_value, append_spacer: yn, truncate_for_space, color }
 	}
 	pub fn truncate_for_space(self, yn: bool) -> Self {
-		let Self { content, append_spacer, truncate_for_space: _, color } = self;
-		Self { content, append_spacer, truncate_for_space: yn, color }
+		let Self { content, sort_value, append_spacer, truncate_for_space: _, color } = self;
+		Self { content, sort_value, append_spacer, truncate_for_space: yn, color }
 	}
 	pub fn with_color(self, color: Color) -> Self {
-		let Self { content, append_spacer, truncate_for_space, color: _ } = self;
-		Self { content, append_spacer, truncate_for_space, color: Some(color) }
+		let Self { content, sort_value, append_spacer, truncate_for_space, color: _ } = self;
+		Self { content, sort_value, append_spacer, truncate_for_space, color: Some(color) }
+	}
+	/// The value this cell sorts by. Cells that weren't given one sort by their text.
+	pub fn sort_value(&self) -> &SortValue {
+		&self.sort_value
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A two column table of command names and the given cells, under the headings "Command" and "Value"
+	fn table_of(rows: Vec<(&str, Cell)>) -> Table {
+		let mut table = Table::new()
+			.with_n_columns(2)
+			.with_heading(0, "Command")
+			.with_heading(1, "Value");
+		for (command, cell) in rows {
+			table.add_row(Row::new().with_cell(Cell::new(command)).with_cell(cell));
+		}
+		table
+	}
+
+	fn commands(table: &Table) -> Vec<&str> {
+		table.rows.iter().map(|row| row.cells[0].content.as_str()).collect()
+	}
+
+	fn percent(pct: f64) -> Cell {
+		Cell::new(format!("{pct:.1}%")).with_sort_value(SortValue::Float(pct))
+	}
+
+	#[test]
+	fn numbers_compare_across_types_and_before_text() {
+		assert_eq!(SortValue::Int(3).cmp(&SortValue::Float(2.5)), Ordering::Greater);
+		assert_eq!(SortValue::Float(2.0).cmp(&SortValue::Int(2)), Ordering::Equal);
+		assert_eq!(SortValue::Duration(60).cmp(&SortValue::Int(61)), Ordering::Less);
+		assert_eq!(SortValue::Int(i64::MAX).cmp(&SortValue::Text("0".into())), Ordering::Less);
+		assert_eq!(SortValue::Text("10".into()).cmp(&SortValue::Text("9".into())), Ordering::Less);
+	}
+
+	#[test]
+	fn natural_order_depends_on_the_value() {
+		assert!(SortValue::Int(0).natural_desc());
+		assert!(SortValue::Float(0.0).natural_desc());
+		assert!(SortValue::Duration(0).natural_desc());
+		assert!(SortValue::Timestamp(0).natural_desc());
+		assert!(!SortValue::Rank(0).natural_desc());
+		assert!(!SortValue::Text(String::new()).natural_desc());
+	}
+
+	#[test]
+	fn percents_sort_by_value_not_text() {
+		let mut table = table_of(vec![("ls", percent(9.0)), ("git", percent(12.5)), ("cd", percent(100.0))]);
+		table.set_sort_column(1);
+		table.sort();
+		assert_eq!(commands(&table), ["cd", "git", "ls"]);
+	}
+
+	#[test]
+	fn bars_sort_by_value_not_glyphs() {
+		let bar = |glyphs: &str, pct: f64| Cell::new(glyphs).with_sort_value(SortValue::Float(pct));
+		let mut table = table_of(vec![("ls", bar("▏", 1.0)), ("git", bar("███▌", 35.0)), ("vim", bar("█", 10.0))]);
+		table.set_sort_column(1);
+		table.sort();
+		assert_eq!(commands(&table), ["git", "vim", "ls"]);
 	}
-	pub fn as_number(&self) -> Option<u64> {
-		self.content.trim().parse::<u64>().ok()
+
+	#[test]
+	fn later_keys_break_ties() {
+		let rows = || vec![("b", Cell::int(1)), ("c", Cell::int(2)), ("a", Cell::int(1))];
+		let mut table = table_of(rows());
+		table.set_sort_column(1);
+		table.add_sort_key(0, SortOrder::Ascending);
+		table.sort();
+		assert_eq!(commands(&table), ["c", "a", "b"]);
+
+		let mut table = table_of(rows());
+		table.add_sort_key(1, SortOrder::Ascending);
+		table.add_sort_key(0, SortOrder::Descending);
+		table.sort();
+		assert_eq!(commands(&table), ["b", "a", "c"]);
+	}
+
+	#[test]
+	fn reverse_flips_every_key() {
+		let mut table = table_of(vec![("b", Cell::int(1)), ("c", Cell::int(2)), ("a", Cell::int(1))]);
+		table.set_sort_column(1);
+		table.add_sort_key(0, SortOrder::Ascending);
+		table.reverse();
+		table.sort();
+		assert_eq!(commands(&table), ["b", "a", "c"]);
+	}
+
+	#[test]
+	fn unsorted_tables_sort_by_the_first_column() {
+		let mut table = table_of(vec![("b", Cell::int(1)), ("a", Cell::int(2))]);
+		table.sort();
+		assert_eq!(commands(&table), ["a", "b"]);
 	}
 }
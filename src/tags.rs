@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{bar_width, get_bar, table::{Cell, Row, SortValue, Table}, Entries, Entry};
+
+impl Entry {
+	/// Whether this entry, or any command grouped into it, is listed under the given tag
+	pub fn has_tag(&self, tags: &BTreeMap<String,Vec<String>>, tag: &str) -> bool {
+		let Some(commands) = tags.get(tag) else {
+			return false
+		};
+		commands.contains(&self.command) || self.member_names().iter().any(|cmd| commands.iter().any(|c| c == cmd))
+	}
+}
+
+impl Entries {
+	/// Keep only entries carrying at least one of `wanted`
+	pub fn retain_tags(&mut self, tags: &BTreeMap<String,Vec<String>>, wanted: &[String]) -> Result<(),String> {
+		if let Some(unknown) = wanted.iter().find(|tag| !tags.contains_key(*tag)) {
+			return Err(format!("cmdstat: no tag named `{unknown}' in the config file"))
+		}
+		self.retain_entries(|ent| wanted.iter().any(|tag| ent.has_tag(tags, tag)));
+		Ok(())
+	}
+}
+
+/// Render a table with one row per tag, showing the calls made to its commands
+/// as a share of all calls
+pub fn format_tags(entries: &Entries, tags: &BTreeMap<String,Vec<String>>, bar_color: Option<Color>) -> Table {
+	let bar_color = bar_color.unwrap_or(Color::Green);
+	let total: usize = entries.0.iter().map(|ent| ent.count as usize).sum();
+	let mut table = Table::new()
+		.with_n_columns(5)
+		.with_heading(0, "Tag")
+		.with_heading(1, "Commands")
+		.with_heading(2, "Count")
+		.with_heading(3, "Percent")
+		.with_heading(4, "Usage")
+		.with_title("Tag Statistics".with(Color::Cyan).bold());
+
+	for tag in tags.keys() {
+		let tagged: Vec<&Entry> = entries.0.iter()
+			.filter(|ent| ent.has_tag(tags, tag))
+			.collect();
+		let count: usize = tagged.iter().map(|ent| ent.count as usize).sum();
+		let percentage = if total == 0 { 0.0 } else { (count as f64 / total as f64) * 100.0 };
+		let row = Row::new()
+			.with_cell(Cell::new(tag))
+			.with_cell(Cell::int(tagged.len()))
+			.with_cell(Cell::int(count))
+			.with_cell(Cell::new(format!("{percentage:.01}%")).with_sort_value(SortValue::Float(percentage)))
+			.with_cell(Cell::new(get_bar(percentage as usize, bar_width()))
+				.with_sort_value(SortValue::Float(percentage))
+				.with_color(bar_color));
+		table.add_row(row);
+	}
+	table.set_sort_column(2);
+	table.sort();
+	table
+}
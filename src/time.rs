@@ -0,0 +1,131 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MINUTE: u64 = 60;
+pub const HOUR: u64 = MINUTE * 60;
+pub const DAY: u64 = HOUR * 24;
+pub const WEEK: u64 = DAY * 7;
+pub const YEAR: u64 = DAY * 365;
+
+/// Current unix time in seconds
+pub fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
+
+/// Parse a duration like '90s', '10m', '12h', '3d', '2w' or '1y' into seconds.
+/// A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<u64,String> {
+	let s = s.trim();
+	let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+	let (num, unit) = s.split_at(split);
+	let num = num.parse::<u64>().map_err(|_| format!("cmdstat: invalid duration `{s}'"))?;
+	let scale = match unit {
+		"" | "s" => 1,
+		"m" => MINUTE,
+		"h" => HOUR,
+		"d" => DAY,
+		"w" => WEEK,
+		"y" => YEAR,
+		_ => return Err(format!("cmdstat: invalid duration unit `{unit}' in `{s}'"))
+	};
+	num.checked_mul(scale).ok_or_else(|| format!("cmdstat: invalid duration `{s}'"))
+}
+
+/// Broken down local time for a unix timestamp
+pub fn local_time(secs: u64) -> libc::tm {
+	let secs = secs as libc::time_t;
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+	unsafe { libc::localtime_r(&secs, &mut tm) };
+	tm
+}
+
+/// Format a unix timestamp as a local `YYYY-MM-DD` date
+pub fn format_date(secs: u64) -> String {
+	let tm = local_time(secs);
+	format!("{:04}-{:02}-{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
+/// Parse a local `YYYY-MM-DD` date into the unix time of its midnight
+pub fn parse_date(s: &str) -> Result<u64,String> {
+	let err = || format!("cmdstat: invalid date `{s}', expected YYYY-MM-DD");
+	let mut parts = s.trim().splitn(3, '-').map(|part| part.parse::<i32>());
+	let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) = (parts.next(), parts.next(), parts.next()) else {
+		return Err(err())
+	};
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+		return Err(err())
+	}
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+	tm.tm_year = year - 1900;
+	tm.tm_mon = month - 1;
+	tm.tm_mday = day;
+	tm.tm_isdst = -1;
+	let secs = unsafe { libc::mktime(&mut tm) };
+	u64::try_from(secs).map_err(|_| err())
+}
+
+/// Format a number of seconds in its largest whole unit, like '3d' or '5h'
+pub fn format_duration(secs: u64) -> String {
+	let (scale, unit) = [(YEAR, "y"), (WEEK, "w"), (DAY, "d"), (HOUR, "h"), (MINUTE, "m")]
+		.into_iter()
+		.find(|&(scale, _)| secs >= scale)
+		.unwrap_or((1, "s"));
+	format!("{}{unit}", secs / scale)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn durations() {
+		assert_eq!(parse_duration("90"), Ok(90));
+		assert_eq!(parse_duration("90s"), Ok(90));
+		assert_eq!(parse_duration("10m"), Ok(10 * MINUTE));
+		assert_eq!(parse_duration("12h"), Ok(12 * HOUR));
+		assert_eq!(parse_duration(" 3d "), Ok(3 * DAY));
+		assert_eq!(parse_duration("2w"), Ok(2 * WEEK));
+		assert_eq!(parse_duration("1y"), Ok(YEAR));
+		assert_eq!(parse_duration("0d"), Ok(0));
+	}
+
+	#[test]
+	fn invalid_durations() {
+		assert_eq!(parse_duration(""), Err("cmdstat: invalid duration `'".to_string()));
+		assert_eq!(parse_duration("d"), Err("cmdstat: invalid duration `d'".to_string()));
+		assert_eq!(parse_duration("-3d"), Err("cmdstat: invalid duration `-3d'".to_string()));
+		assert_eq!(parse_duration("3x"), Err("cmdstat: invalid duration unit `x' in `3x'".to_string()));
+		assert_eq!(parse_duration("3 d"), Err("cmdstat: invalid duration unit ` d' in `3 d'".to_string()));
+		assert_eq!(parse_duration("99999999999999999y"), Err("cmdstat: invalid duration `99999999999999999y'".to_string()));
+		assert!(parse_duration("99999999999999999999").is_err());
+	}
+
+	#[test]
+	fn dates_round_trip() {
+		for date in ["1970-01-02", "2024-02-29", "2025-12-31"] {
+			assert_eq!(format_date(parse_date(date).unwrap()), date);
+		}
+		let (a, b) = (parse_date("2025-01-01").unwrap(), parse_date("2025-01-02").unwrap());
+		assert!((DAY - HOUR..=DAY + HOUR).contains(&(b - a)));
+	}
+
+	#[test]
+	fn invalid_dates() {
+		let err = |s: &str| format!("cmdstat: invalid date `{s}', expected YYYY-MM-DD");
+		for date in ["", "yesterday", "2025-01", "2025-13-01", "2025-00-10", "2025-01-32", "2025-01-xx"] {
+			assert_eq!(parse_date(date), Err(err(date)));
+		}
+	}
+
+	#[test]
+	fn formats_in_the_largest_whole_unit() {
+		assert_eq!(format_duration(0), "0s");
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(90), "1m");
+		assert_eq!(format_duration(3 * DAY + HOUR), "3d");
+		assert_eq!(format_duration(13 * DAY), "1w");
+		assert_eq!(format_duration(400 * DAY), "1y");
+	}
+}
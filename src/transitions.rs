@@ -0,0 +1,105 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap}, fmt::Write};
+
+use crossterm::style::{Color, Stylize};
+
+use crate::{bar_width, events::{Event, Events}, get_bar, table::{Cell, Row, SortValue, Table}};
+
+/// Which neighbour of a command to look at
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Direction {
+	Next,
+	Prev,
+}
+
+/// A command, optionally narrowed down to one subcommand
+#[derive(Debug)]
+pub struct Pattern<'a> {
+	pub command: &'a str,
+	pub sub: Option<&'a str>,
+	/// Commands grouped under `command` in the config file
+	pub members: &'a [String],
+}
+
+impl Pattern<'_> {
+	fn matches(&self, event: &Event) -> bool {
+		(event.command == self.command || self.members.contains(&event.command)) &&
+		self.sub.is_none_or(|sub| event.sub.as_deref() == Some(sub))
+	}
+}
+
+impl std::fmt::Display for Pattern<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.sub {
+			Some(sub) => write!(f, "{} {sub}", self.command),
+			None => write!(f, "{}", self.command),
+		}
+	}
+}
+
+/// The command and subcommand an event was recorded with
+fn label(event: &Event) -> String {
+	match &event.sub {
+		Some(sub) => format!("{} {sub}", event.command),
+		None => event.command.clone(),
+	}
+}
+
+/// Count the commands run straight after (or before) each call matching `pattern` within the
+/// same shell session, busiest first. Events without a session are skipped.
+pub fn transitions(events: &Events, pattern: &Pattern, direction: Direction) -> Vec<(String,u32)> {
+	let mut sessions: HashMap<&str, Vec<&Event>> = HashMap::new();
+	for event in events.0.iter().filter(|ev| !ev.session.is_empty()) {
+		sessions.entry(&event.session).or_default().push(event);
+	}
+	let mut counts: BTreeMap<String,u32> = BTreeMap::new();
+	for session in sessions.values_mut() {
+		// Events are appended by background jobs racing for the lock, so the file order can be off
+		session.sort_by_key(|ev| ev.time);
+		for pair in session.windows(2) {
+			let (anchor, neighbour) = match direction {
+				Direction::Next => (pair[0], pair[1]),
+				Direction::Prev => (pair[1], pair[0]),
+			};
+			if pattern.matches(anchor) {
+				*counts.entry(label(neighbour)).or_default() += 1;
+			}
+		}
+	}
+	let mut counts: Vec<(String,u32)> = counts.into_iter().collect();
+	counts.sort_by_key(|&(_, n)| Reverse(n));
+	counts
+}
+
+pub fn format_transitions(counts: &[(String,u32)], pattern: &Pattern, direction: Direction, num: usize, bar_color: Option<Color>) -> String {
+	let bar_color = bar_color.unwrap_or(Color::Green);
+	let total: u32 = counts.iter().map(|(_, n)| n).sum();
+	let mut output = String::new();
+	let (title, relation) = match direction {
+		Direction::Next => (format!("After {pattern}"), "after"),
+		Direction::Prev => (format!("Before {pattern}"), "before"),
+	};
+	if counts.is_empty() {
+		writeln!(output, "No commands have been recorded {relation} `{pattern}' within the same session.").unwrap();
+		return output
+	}
+	let mut table = Table::new()
+		.with_n_columns(4)
+		.with_heading(0, "Command")
+		.with_heading(1, "Count")
+		.with_heading(2, "Percent")
+		.with_heading(3, "Usage")
+		.with_title(format!("{} {}", title.with(Color::Cyan).bold(), format!("({total} calls)").with(Color::DarkGrey)));
+	for (cmd, count) in counts.iter().take(num) {
+		let percentage = (*count as f64 / total as f64) * 100.0;
+		let row = Row::new()
+			.with_cell(Cell::new(cmd))
+			.with_cell(Cell::int(*count))
+			.with_cell(Cell::new(format!("{percentage:.01}%")).with_sort_value(SortValue::Float(percentage)))
+			.with_cell(Cell::new(get_bar(percentage as usize, bar_width()))
+				.with_sort_value(SortValue::Float(percentage))
+				.with_color(bar_color));
+		table.add_row(row);
+	}
+	writeln!(output, "{table}").unwrap();
+	output
+}
@@ -0,0 +1,154 @@
+use std::{env, fmt::Write, fs, io, path::{Path, PathBuf}};
+
+use crossterm::style::{Color, Stylize};
+use serde::Deserialize;
+
+use crate::{stats_file, table::{Cell, Row, Table}, CmdKind, Entries};
+
+/// A command name that didn't resolve to anything when it was run
+#[derive(Deserialize,Debug)]
+pub struct Miss {
+	pub command: String,
+	pub count: u32,
+}
+
+/// A miss paired with the closest known command, if one is close enough
+#[derive(Debug)]
+pub struct Suggestion<'a> {
+	pub miss: &'a Miss,
+	pub closest: Option<(&'a str, usize)>,
+}
+
+pub fn misses_file() -> PathBuf {
+	if let Ok(var) = env::var("CMDSTAT_MISSES") {
+		var.into()
+	} else {
+		stats_file().with_file_name("misses.json")
+	}
+}
+
+pub fn read_misses() -> Vec<Miss> {
+	let raw = fs::read_to_string(misses_file()).unwrap_or_default();
+	let mut misses: Vec<Miss> = serde_json::from_str(&raw).unwrap_or_default();
+	misses.sort_by(|a, b| b.count.cmp(&a.count).then(a.command.cmp(&b.command)));
+	misses
+}
+
+/// Edit distance between two strings, counted in chars. Insertions, deletions,
+/// substitutions and swaps of adjacent chars each cost 1, since swaps are the most common typo.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let a: Vec<char> = a.chars().collect();
+	let b: Vec<char> = b.chars().collect();
+	let mut dist = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in dist.iter_mut().enumerate() {
+		row[0] = i;
+	}
+	for (j, cell) in dist[0].iter_mut().enumerate() {
+		*cell = j;
+	}
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let cost = usize::from(a[i - 1] != b[j - 1]);
+			dist[i][j] = (dist[i - 1][j] + 1)
+				.min(dist[i][j - 1] + 1)
+				.min(dist[i - 1][j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				dist[i][j] = dist[i][j].min(dist[i - 2][j - 2] + 1);
+			}
+		}
+	}
+	dist[a.len()][b.len()]
+}
+
+/// Pair each miss with the known command nearest to it by edit distance.
+/// Ties go to the more frequently used command. Commands further away than a third
+/// of the miss's length (but at least 1) aren't considered a match.
+pub fn suggest<'a>(misses: &'a [Miss], entries: &'a Entries) -> Vec<Suggestion<'a>> {
+	misses.iter()
+		.map(|miss| {
+			let max_dist = (miss.command.chars().count() / 3).max(1);
+			let closest = entries.0.iter()
+				.filter(|ent| ent.kind != CmdKind::Script)
+				.map(|ent| (ent, edit_distance(&miss.command, &ent.command)))
+				.filter(|&(_, dist)| dist <= max_dist)
+				.min_by(|(a, da), (b, db)| da.cmp(db).then(b.count.cmp(&a.count)))
+				.map(|(ent, dist)| (ent.command.as_str(), dist));
+			Suggestion { miss, closest }
+		})
+		.collect()
+}
+
+pub fn format_typos(suggestions: &[Suggestion]) -> Table {
+	let mut table = Table::new()
+		.with_n_columns(4)
+		.with_heading(0, "Typo")
+		.with_heading(1, "Count")
+		.with_heading(2, "Suggestion")
+		.with_heading(3, "Distance")
+		.with_title("Command Not Found".with(Color::Cyan).bold());
+	for Suggestion { miss, closest } in suggestions {
+		let (suggestion, dist) = match closest {
+			Some((cmd, dist)) => (Cell::new(cmd).with_color(Color::Green), Cell::int(*dist)),
+			None => (Cell::new("-"), Cell::new("-")),
+		};
+		let row = Row::new()
+			.with_cell(Cell::new(&miss.command))
+			.with_cell(Cell::int(miss.count))
+			.with_cell(suggestion)
+			.with_cell(dist);
+		table.add_row(row);
+	}
+	table
+}
+
+/// Whether a name can be used as an alias without quoting
+fn is_alias_safe(name: &str) -> bool {
+	!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+}
+
+/// Write a sourceable file with an alias from each typo to its suggested command
+pub fn write_aliases(path: &Path, suggestions: &[Suggestion]) -> io::Result<usize> {
+	let mut file = String::from("# Generated by `cmdstat typos`\n");
+	let mut written = 0;
+	for Suggestion { miss, closest } in suggestions {
+		let Some((cmd, _)) = closest else {
+			continue
+		};
+		if !is_alias_safe(&miss.command) || !is_alias_safe(cmd) {
+			continue
+		}
+		writeln!(file, "alias {}={cmd}", miss.command).unwrap();
+		written += 1;
+	}
+	fs::write(path, file)?;
+	Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::edit_distance;
+
+	#[test]
+	fn distances() {
+		assert_eq!(edit_distance("git", "git"), 0);
+		assert_eq!(edit_distance("", "git"), 3);
+		assert_eq!(edit_distance("git", ""), 3);
+		assert_eq!(edit_distance("gi", "git"), 1);
+		assert_eq!(edit_distance("gut", "git"), 1);
+		assert_eq!(edit_distance("kitten", "sitting"), 3);
+	}
+
+	#[test]
+	fn swaps_cost_one() {
+		assert_eq!(edit_distance("gti", "git"), 1);
+		assert_eq!(edit_distance("sl", "ls"), 1);
+		assert_eq!(edit_distance("dokcer", "docker"), 1);
+		assert_eq!(edit_distance("tgi", "git"), 2);
+	}
+
+	#[test]
+	fn counts_chars_not_bytes() {
+		assert_eq!(edit_distance("café", "cafe"), 1);
+		assert_eq!(edit_distance("éa", "aé"), 1);
+	}
+}